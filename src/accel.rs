@@ -0,0 +1,153 @@
+//! Acceleration structure builds. Each build creates its own input and scratch buffers, runs on the active queue of
+//! the [`RtContext`] and waits for completion, so the returned structure is ready to be traced against.
+
+use ash::vk;
+
+use crate::{
+    context::RtContext,
+    error::{Context, Result},
+    memory::Placement,
+    owned::AccelerationStructure,
+};
+
+/// Builds a bottom-level acceleration structure holding one opaque procedural geometry made of `aabbs`.
+pub unsafe fn build_aabb_blas(ctx: &RtContext, aabbs: &[vk::AabbPositionsKHR]) -> Result<AccelerationStructure> {
+    let blas_input_buf = ctx.create_buffer(
+        "blas_input_buf",
+        std::mem::size_of_val(aabbs) as u64,
+        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        Placement::HostVisible,
+    )?;
+    ctx.write_buffer(&blas_input_buf, aabbs)?;
+    let geometry = vk::AccelerationStructureGeometryKHR {
+        geometry_type: vk::GeometryTypeKHR::AABBS,
+        geometry: vk::AccelerationStructureGeometryDataKHR {
+            aabbs: vk::AccelerationStructureGeometryAabbsDataKHR {
+                data: vk::DeviceOrHostAddressConstKHR {
+                    device_address: ctx.buffer_address(&blas_input_buf),
+                },
+                stride: std::mem::size_of::<vk::AabbPositionsKHR>() as u64,
+                ..Default::default()
+            },
+        },
+        flags: vk::GeometryFlagsKHR::OPAQUE,
+        ..Default::default()
+    };
+    build(ctx, "blas", vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL, geometry, aabbs.len() as u32)
+}
+
+/// Builds a top-level acceleration structure with a single instance of `blas` at the identity transform.
+pub unsafe fn build_tlas(ctx: &RtContext, blas: &AccelerationStructure) -> Result<AccelerationStructure> {
+    let instances = [vk::AccelerationStructureInstanceKHR {
+        transform: vk::TransformMatrixKHR {
+            matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        },
+        instance_custom_index_and_mask: vk::Packed24_8::new(0, u8::MAX),
+        instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(0, 0),
+        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+            device_handle: ctx.accel_struct_loader.get_acceleration_structure_device_address(
+                &vk::AccelerationStructureDeviceAddressInfoKHR {
+                    acceleration_structure: blas.handle,
+                    ..Default::default()
+                },
+            ),
+        },
+    }];
+    let tlas_input_buf = ctx.create_buffer(
+        "tlas_input_buf",
+        std::mem::size_of_val(&instances) as u64,
+        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        Placement::HostVisible,
+    )?;
+    ctx.write_buffer(&tlas_input_buf, &instances)?;
+    let geometry = vk::AccelerationStructureGeometryKHR {
+        geometry_type: vk::GeometryTypeKHR::INSTANCES,
+        geometry: vk::AccelerationStructureGeometryDataKHR {
+            instances: vk::AccelerationStructureGeometryInstancesDataKHR {
+                array_of_pointers: vk::FALSE,
+                data: vk::DeviceOrHostAddressConstKHR {
+                    device_address: ctx.buffer_address(&tlas_input_buf),
+                },
+                ..Default::default()
+            },
+        },
+        flags: vk::GeometryFlagsKHR::OPAQUE,
+        ..Default::default()
+    };
+    build(ctx, "tlas", vk::AccelerationStructureTypeKHR::TOP_LEVEL, geometry, instances.len() as u32)
+}
+
+/// Creates an acceleration structure named `name` along with its backing buffer, and builds it from `geometry`.
+/// The buffers `geometry` points into must stay alive until this returns.
+unsafe fn build(
+    ctx: &RtContext,
+    name: &str,
+    ty: vk::AccelerationStructureTypeKHR,
+    geometry: vk::AccelerationStructureGeometryKHR,
+    primitive_count: u32,
+) -> Result<AccelerationStructure> {
+    let loader = &ctx.accel_struct_loader;
+    let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR {
+        ty,
+        flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+        mode: vk::BuildAccelerationStructureModeKHR::BUILD,
+        geometry_count: 1,
+        p_geometries: &geometry,
+        ..Default::default()
+    };
+    let build_sizes = loader.get_acceleration_structure_build_sizes(
+        vk::AccelerationStructureBuildTypeKHR::DEVICE,
+        &build_info,
+        &[primitive_count],
+    );
+    let backing_buf = ctx.create_buffer(
+        &format!("{name}_backing_buf"),
+        build_sizes.acceleration_structure_size,
+        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR,
+        Placement::DeviceLocal,
+    )?;
+    let handle = loader
+        .create_acceleration_structure(
+            &vk::AccelerationStructureCreateInfoKHR {
+                buffer: backing_buf.buffer,
+                offset: 0,
+                size: build_sizes.acceleration_structure_size,
+                ty,
+                ..Default::default()
+            },
+            None,
+        )
+        .context_sized(format!("create {name}"), build_sizes.acceleration_structure_size)?;
+    let accel_struct = AccelerationStructure::new(loader.clone(), handle, backing_buf);
+    ctx.names.name(handle, name)?;
+
+    // The scratch address must be a multiple of minAccelerationStructureScratchOffsetAlignment, which the memory
+    // requirements of the buffer don't necessarily account for.
+    let scratch_buf = ctx.create_buffer_at(
+        &format!("{name}_scratch_buf"),
+        build_sizes.build_scratch_size,
+        vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        Placement::DeviceLocal,
+        0,
+        ctx.accel_struct_properties.min_acceleration_structure_scratch_offset_alignment as u64,
+    )?;
+    build_info.dst_acceleration_structure = handle;
+    build_info.scratch_data = vk::DeviceOrHostAddressKHR {
+        device_address: ctx.buffer_address(&scratch_buf),
+    };
+    ctx.execute(&format!("{} build", name.to_uppercase()), |command_buffer| {
+        loader.cmd_build_acceleration_structures(
+            command_buffer,
+            &[build_info],
+            &[&[vk::AccelerationStructureBuildRangeInfoKHR {
+                primitive_count,
+                primitive_offset: 0,
+                first_vertex: 0,
+                transform_offset: 0,
+            }]],
+        );
+    })?;
+    Ok(accel_struct)
+}
@@ -0,0 +1,139 @@
+//! A block sub-allocator, which places buffers side by side inside large shared allocations instead of giving each
+//! its own. Drivers hand out dedicated allocations at generous alignments, so this is how buffers end up at the
+//! offsets and device addresses real applications see.
+//!
+//! Blocks are filled linearly, one memory type at a time, and a block is only reused from its start once every
+//! buffer placed in it is gone. That is plenty for a test harness that creates a handful of buffers.
+
+use std::{cell::RefCell, rc::Rc};
+
+use ash::vk;
+
+use crate::{error::Result, owned::Memory, sbt::align_up};
+
+/// Size of the blocks the harness sub-allocates from, large enough for every buffer of a trace.
+pub const DEFAULT_BLOCK_SIZE: u64 = 64 << 20;
+
+struct Block {
+    memory: Rc<Memory>,
+    /// Offset of the first free byte.
+    next: u64,
+}
+
+pub struct SubAllocator {
+    /// Size of new blocks. Buffers that don't fit get a block of their own, just large enough.
+    pub block_size: u64,
+    blocks: RefCell<Vec<Block>>,
+}
+
+impl SubAllocator {
+    pub fn new(block_size: u64) -> Self {
+        Self {
+            block_size,
+            blocks: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Finds room for `size` bytes at a multiple of `alignment` that is at least `min_offset` in a block of memory
+    /// type `memory_type_index`, calling `allocate_block` with the size of a new block if none has room. Returns the
+    /// block and the offset in it.
+    pub fn allocate(
+        &self,
+        size: u64,
+        alignment: u64,
+        min_offset: u64,
+        memory_type_index: u32,
+        allocate_block: impl FnOnce(u64) -> Result<Rc<Memory>>,
+    ) -> Result<(Rc<Memory>, u64)> {
+        let mut blocks = self.blocks.borrow_mut();
+        for block in blocks.iter_mut() {
+            if block.memory.memory_type_index != memory_type_index {
+                continue;
+            }
+            // Only the allocator itself still refers to the block, so all of it is free again.
+            if Rc::strong_count(&block.memory) == 1 {
+                block.next = 0;
+            }
+            if let Some(offset) = place(block.next, size, alignment, min_offset, block.memory.size) {
+                block.next = offset + size;
+                return Ok((block.memory.clone(), offset));
+            }
+        }
+        let offset = align_up(min_offset, alignment);
+        let memory = allocate_block(self.block_size.max(offset + size))?;
+        blocks.push(Block {
+            memory: memory.clone(),
+            next: offset + size,
+        });
+        Ok((memory, offset))
+    }
+}
+
+/// The `(size, alignment)` to bind a buffer with, from its memory `requirements` and the `min_alignment` its use needs
+/// on top of them. `atom_size` is the `nonCoherentAtomSize` if the host flushes and invalidates the buffer: those
+/// ranges are widened to whole atoms, so the buffer gets whole atoms of its own and never shares one with a neighbour.
+pub fn bind_layout(requirements: &vk::MemoryRequirements, min_alignment: u64, atom_size: Option<u64>) -> (u64, u64) {
+    let alignment = requirements.alignment.max(min_alignment);
+    match atom_size {
+        Some(atom_size) => (align_up(requirements.size, atom_size), alignment.max(atom_size)),
+        None => (requirements.size, alignment),
+    }
+}
+
+/// The offset at which `size` bytes aligned to `alignment` go in a block of `block_size` bytes whose first free byte
+/// is at `next`, no lower than `min_offset`. Returns `None` if they don't fit.
+pub fn place(next: u64, size: u64, alignment: u64, min_offset: u64, block_size: u64) -> Option<u64> {
+    let offset = align_up(next.max(min_offset), alignment);
+    (offset.checked_add(size)? <= block_size).then_some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::atom_range;
+
+    #[test]
+    fn places_aligned() {
+        assert_eq!(place(0, 1000, 256, 0, 4096), Some(0));
+        assert_eq!(place(1000, 1000, 256, 0, 4096), Some(1024));
+        assert_eq!(place(1000, 1000, 64, 0, 4096), Some(1024));
+        assert_eq!(place(1000, 1000, 16, 0, 4096), Some(1008));
+        // Exactly filling the block.
+        assert_eq!(place(3072, 1024, 256, 0, 4096), Some(3072));
+    }
+
+    #[test]
+    fn respects_min_offset() {
+        assert_eq!(place(0, 1000, 64, 100, 4096), Some(128));
+        assert_eq!(place(2048, 1000, 64, 100, 4096), Some(2048));
+        assert_eq!(place(0, 1000, 64, 4096, 4096), None);
+    }
+
+    #[test]
+    fn rejects_what_doesnt_fit() {
+        assert_eq!(place(3073, 1024, 256, 0, 4096), None);
+        assert_eq!(place(0, 5000, 256, 0, 4096), None);
+        assert_eq!(place(u64::MAX - 10, 100, 1, 0, u64::MAX), None);
+    }
+
+    #[test]
+    fn keeps_non_coherent_atoms_apart() {
+        let requirements = |size| vk::MemoryRequirements {
+            size,
+            alignment: 16,
+            memory_type_bits: 1,
+        };
+        // Coherent memory only needs the alignment of the requirements and of the buffer's use.
+        assert_eq!(bind_layout(&requirements(1000), 1, None), (1000, 16));
+        assert_eq!(bind_layout(&requirements(1000), 128, None), (1000, 128));
+        // Non-coherent buffers take whole atoms, so the atoms flushed for one end where the next one starts.
+        let (first_size, first_alignment) = bind_layout(&requirements(1000), 1, Some(256));
+        assert_eq!((first_size, first_alignment), (1024, 256));
+        let first = place(0, first_size, first_alignment, 0, 4096).unwrap();
+        let (second_size, second_alignment) = bind_layout(&requirements(40), 64, Some(256));
+        let second = place(first + first_size, second_size, second_alignment, 0, 4096).unwrap();
+        assert_eq!((first, second), (0, 1024));
+        assert_eq!(atom_range(first, 1000, 256, 4096), (0, 1024));
+        assert_eq!(atom_range(second, 40, 256, 4096), (1024, 256));
+    }
+}
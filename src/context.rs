@@ -0,0 +1,547 @@
+//! [`RtContext`], the device-level state every ray tracing test needs: the device with its queues, the extension
+//! loaders and the ray tracing properties, plus helpers to create buffers and run one-off command buffers.
+
+use std::{rc::Rc, time::Duration};
+
+use ash::{
+    extensions::khr::{AccelerationStructure, RayTracingPipeline},
+    vk,
+};
+
+use crate::{
+    allocator::{self, SubAllocator},
+    error::{Context, Error, Result},
+    fault::DeviceFault,
+    memory::{self, Placement},
+    names::DebugNames,
+    owned::{Buffer, Device, Memory, Owned},
+    probe::{self, DeviceInfo, QueueFamily, Skipped, Vulkan},
+    sbt::align_up,
+};
+
+/// How long [`RtContext::execute`] waits for a submission unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A queue and a command buffer allocated for its family, with the fence its submissions signal.
+pub struct FamilyQueue {
+    pub family: QueueFamily,
+    pub queue: vk::Queue,
+    pub command_buffer: vk::CommandBuffer,
+    fence: Owned<vk::Fence>,
+    _command_pool: Owned<vk::CommandPool>,
+}
+
+pub struct RtContext {
+    pub device: Rc<Device>,
+    /// One queue per queue family the context submits to. Only the first compute-capable family is used unless
+    /// all of them were asked for.
+    pub queues: Vec<FamilyQueue>,
+    /// Index into `queues` of the queue [`RtContext::execute`] submits to.
+    pub active_queue: usize,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub non_coherent_atom_size: u64,
+    /// Flush host writes and invalidate before host reads even on coherent memory, to rule out coherency problems.
+    pub force_non_coherent: bool,
+    /// Places buffers inside shared blocks of memory when set, instead of giving each its own allocation.
+    pub allocator: Option<SubAllocator>,
+    /// How long [`RtContext::execute`] waits for a submission to complete before reporting a GPU timeout.
+    pub timeout: Duration,
+    pub accel_struct_loader: AccelerationStructure,
+    pub rtx_pipeline_loader: RayTracingPipeline,
+    pub rtx_pipeline_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR,
+    pub accel_struct_properties: vk::PhysicalDeviceAccelerationStructurePropertiesKHR,
+    pub names: DebugNames,
+    /// Set if the device has `VK_EXT_device_fault`, to explain why it was lost. Otherwise a lost device goes
+    /// unexplained.
+    pub device_fault: Option<DeviceFault>,
+}
+
+impl RtContext {
+    /// Creates a device on `device` with the ray tracing extensions and features enabled, or returns what the system
+    /// is missing to run the tests. With `all_queue_families`, one queue of every compute-capable family is created.
+    pub unsafe fn new(vulkan: &Vulkan, device: &DeviceInfo, all_queue_families: bool) -> Result<Self> {
+        let instance = &vulkan.instance;
+        let pdevice = device.handle;
+        let missing = probe::missing_capabilities(instance, pdevice)?;
+        if !missing.is_empty() {
+            return Err(Skipped { missing }.into());
+        }
+
+        let mut rtx_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR {
+            ray_tracing_pipeline: vk::TRUE,
+            ..Default::default()
+        };
+        let mut accel_struct_features = vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
+            acceleration_structure: vk::TRUE,
+            ..Default::default()
+        };
+        let mut v12_features = vk::PhysicalDeviceVulkan12Features {
+            buffer_device_address: vk::TRUE,
+            ..Default::default()
+        };
+        let mut v13_features = vk::PhysicalDeviceVulkan13Features {
+            synchronization2: vk::TRUE,
+            ..Default::default()
+        };
+        let mut fault_features = vk::PhysicalDeviceFaultFeaturesEXT {
+            device_fault: vk::TRUE,
+            ..Default::default()
+        };
+        let mut extensions = probe::REQUIRED_EXTENSIONS.map(|name| name.as_ptr()).to_vec();
+        let mut features = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut rtx_features)
+            .push_next(&mut accel_struct_features)
+            .push_next(&mut v12_features)
+            .push_next(&mut v13_features);
+        let device_fault = probe::device_fault_supported(instance, pdevice)?;
+        if device_fault {
+            extensions.push(vk::ExtDeviceFaultFn::name().as_ptr());
+            features = features.push_next(&mut fault_features);
+        }
+        let features = features.build();
+        let mut families = probe::compute_queue_families(instance, pdevice);
+        if !all_queue_families {
+            families.truncate(1);
+        }
+        let queue_create_infos = families
+            .iter()
+            .map(|family| vk::DeviceQueueCreateInfo {
+                queue_family_index: family.index,
+                queue_count: 1,
+                p_queue_priorities: &1.0,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let device = instance
+            .create_device(
+                pdevice,
+                &vk::DeviceCreateInfo {
+                    p_next: &features as *const _ as *const _,
+                    queue_create_info_count: queue_create_infos.len() as u32,
+                    p_queue_create_infos: queue_create_infos.as_ptr(),
+                    enabled_extension_count: extensions.len() as u32,
+                    pp_enabled_extension_names: extensions.as_ptr(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .context("create device")?;
+        let device = Device::new(instance, device);
+        let names = DebugNames::new(vulkan.debug_utils.clone(), &device);
+
+        let queues = families
+            .iter()
+            .map(|&family| {
+                // Every submission re-records the same command buffer.
+                let command_pool = device
+                    .create_command_pool(
+                        &vk::CommandPoolCreateInfo {
+                            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+                            queue_family_index: family.index,
+                            ..Default::default()
+                        },
+                        None,
+                    )
+                    .context(format!("create {family} command pool"))?;
+                let command_pool = Owned::new(&device, command_pool);
+                let command_buffer = device
+                    .allocate_command_buffers(&vk::CommandBufferAllocateInfo {
+                        command_pool: *command_pool,
+                        level: vk::CommandBufferLevel::PRIMARY,
+                        command_buffer_count: 1,
+                        ..Default::default()
+                    })
+                    .context(format!("allocate {family} command buffer"))?[0];
+                let fence = device
+                    .create_fence(&Default::default(), None)
+                    .context(format!("create {family} fence"))?;
+                let fence = Owned::new(&device, fence);
+                let queue = device.get_device_queue(family.index, 0);
+                names.name(*fence, &format!("{family} fence"))?;
+                names.name(*command_pool, &format!("{family} command pool"))?;
+                names.name(command_buffer, &format!("{family} command buffer"))?;
+                names.name(queue, &format!("{family} queue"))?;
+                Ok(FamilyQueue {
+                    family,
+                    queue,
+                    command_buffer,
+                    fence,
+                    _command_pool: command_pool,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut properties = vk::PhysicalDeviceProperties2::default();
+        let mut rtx_pipeline_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR =
+            vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+        let mut accel_struct_properties = vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
+        properties.p_next = &mut rtx_pipeline_properties as *mut _ as *mut _;
+        rtx_pipeline_properties.p_next = &mut accel_struct_properties as *mut _ as *mut _;
+        instance.get_physical_device_properties2(pdevice, &mut properties);
+        // Both are kept, so don't leave them pointing at each other.
+        rtx_pipeline_properties.p_next = std::ptr::null_mut();
+
+        Ok(Self {
+            accel_struct_loader: AccelerationStructure::new(instance, &device),
+            rtx_pipeline_loader: RayTracingPipeline::new(instance, &device),
+            device_fault: device_fault.then(|| DeviceFault::new(instance, &device)),
+            device,
+            queues,
+            active_queue: 0,
+            memory_properties: instance.get_physical_device_memory_properties(pdevice),
+            non_coherent_atom_size: properties.properties.limits.non_coherent_atom_size,
+            force_non_coherent: false,
+            allocator: None,
+            timeout: DEFAULT_TIMEOUT,
+            rtx_pipeline_properties,
+            accel_struct_properties,
+            names,
+        })
+    }
+
+    /// Creates a buffer bound to memory from a memory type that suits `placement`, either its own allocation or a
+    /// block of the [`SubAllocator`] if there is one. Buffers used by more than one queue family are shared
+    /// concurrently, so that no ownership transfers are needed between submissions on different families.
+    pub unsafe fn create_buffer(
+        &self,
+        name: &str,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        placement: Placement,
+    ) -> Result<Buffer> {
+        self.create_buffer_at(name, size, usage, placement, 0, 1)
+    }
+
+    /// Like [`RtContext::create_buffer`], binding the buffer at the first offset in its memory that is at least
+    /// `min_offset`, meets its alignment requirements and is a multiple of `min_alignment`, for alignments that the
+    /// use of the buffer needs on top of its memory requirements. Without a sub-allocator, the buffer's own allocation
+    /// grows to leave room for the offset.
+    pub unsafe fn create_buffer_at(
+        &self,
+        name: &str,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        placement: Placement,
+        min_offset: u64,
+        min_alignment: u64,
+    ) -> Result<Buffer> {
+        let device = &self.device;
+        let queue_families = self.queues.iter().map(|queue| queue.family.index).collect::<Vec<_>>();
+        let sharing_mode = if queue_families.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+        let buf = device
+            .create_buffer(
+                &vk::BufferCreateInfo {
+                    size,
+                    usage: usage | placement.extra_usage(),
+                    sharing_mode,
+                    queue_family_index_count: queue_families.len() as u32,
+                    p_queue_family_indices: queue_families.as_ptr(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .context_sized(format!("create {name}"), size)?;
+        // Owned from here on, so that the buffer is destroyed if anything below fails.
+        let mut buffer = Buffer::new(device, name, buf, size, placement);
+
+        let requirements = device.get_buffer_memory_requirements(buf);
+        let (memory_type_index, memory_flags) =
+            memory::find_memory_type(&self.memory_properties, requirements.memory_type_bits, placement).ok_or_else(
+                || Error::NoMemoryType {
+                    operation: format!("create {name}"),
+                    placement,
+                    type_bits: requirements.memory_type_bits,
+                },
+            )?;
+        let mut min_alignment = min_alignment;
+        if usage.contains(vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR) {
+            // SBT regions must start at multiples of shaderGroupBaseAlignment, which the memory requirements of
+            // drivers don't necessarily account for.
+            min_alignment = min_alignment.max(self.rtx_pipeline_properties.shader_group_base_alignment as u64);
+        }
+        let host_visible = memory_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let flushed = host_visible
+            && (self.force_non_coherent || !memory_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT));
+        let (placed_size, alignment) =
+            allocator::bind_layout(&requirements, min_alignment, flushed.then_some(self.non_coherent_atom_size));
+        let (memory, offset) = match &self.allocator {
+            Some(allocator) => {
+                allocator.allocate(placed_size, alignment, min_offset, memory_type_index, |block_size| {
+                    let block_name = format!("memory type {memory_type_index} block");
+                    self.allocate_memory(&block_name, block_size, memory_type_index, memory_flags)
+                })?
+            }
+            None => {
+                let offset = align_up(min_offset, alignment);
+                let memory = self.allocate_memory(
+                    &format!("{name} memory"),
+                    offset + placed_size,
+                    memory_type_index,
+                    memory_flags,
+                )?;
+                (memory, offset)
+            }
+        };
+        device
+            .bind_buffer_memory(buf, memory.memory, offset)
+            .context(format!("bind {name} memory"))?;
+        buffer.set_memory(memory, offset);
+        self.names.name(buf, name)?;
+        Ok(buffer)
+    }
+
+    /// Allocates `size` bytes of memory type `memory_type_index`, which has `flags`, for buffers with device
+    /// addresses.
+    unsafe fn allocate_memory(
+        &self,
+        name: &str,
+        size: u64,
+        memory_type_index: u32,
+        flags: vk::MemoryPropertyFlags,
+    ) -> Result<Rc<Memory>> {
+        let allocate_flags = vk::MemoryAllocateFlagsInfo {
+            flags: vk::MemoryAllocateFlags::DEVICE_ADDRESS,
+            ..Default::default()
+        };
+        let memory = self
+            .device
+            .allocate_memory(
+                &vk::MemoryAllocateInfo {
+                    allocation_size: size,
+                    memory_type_index,
+                    p_next: &allocate_flags as *const _ as *const _,
+                    ..Default::default()
+                },
+                None,
+            )
+            .context_sized(format!("allocate {name}"), size)?;
+        let memory = Memory::new(&self.device, name, memory, size, memory_type_index, flags);
+        self.names.name(memory.memory, name)?;
+        Ok(Rc::new(memory))
+    }
+
+    /// Describes where `buffer` ended up: its device address and its offset in the memory it is bound to.
+    pub unsafe fn describe_buffer(&self, buffer: &Buffer) -> String {
+        let memory = buffer.memory();
+        format!(
+            "{} at device address {:#x}, offset {} in {} (memory type {}, {:?})",
+            buffer.name,
+            self.buffer_address(buffer),
+            buffer.memory_offset,
+            memory.name,
+            memory.memory_type_index,
+            memory.flags
+        )
+    }
+
+    /// Whether host access to `buffer` needs explicit flushes and invalidations.
+    pub fn non_coherent(&self, buffer: &Buffer) -> bool {
+        self.force_non_coherent || !buffer.memory().flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+    }
+
+    /// Maps `buffer`, which must be host-visible, and calls `f` with its bytes. On non-coherent memory, device writes
+    /// are invalidated before `f` runs and, with `write`, the host writes of `f` are flushed after it.
+    unsafe fn with_mapped<R>(&self, buffer: &Buffer, write: bool, f: impl FnOnce(&mut [u8]) -> R) -> Result<R> {
+        let device = &self.device;
+        let memory = buffer.memory();
+        // Map the whole allocation, since the flushed range may extend past either end of the buffer.
+        let ptr = device
+            .map_memory(memory.memory, 0, vk::WHOLE_SIZE, Default::default())
+            .context(format!("map {}", buffer.name))? as *mut u8;
+        let (offset, size) =
+            memory::atom_range(buffer.memory_offset, buffer.size, self.non_coherent_atom_size, memory.size);
+        let range = vk::MappedMemoryRange {
+            memory: memory.memory,
+            offset,
+            size,
+            ..Default::default()
+        };
+        let non_coherent = self.non_coherent(buffer);
+        let result = (|| {
+            if non_coherent {
+                device
+                    .invalidate_mapped_memory_ranges(&[range])
+                    .context(format!("invalidate {}", buffer.name))?;
+            }
+            let result = f(std::slice::from_raw_parts_mut(ptr.add(buffer.memory_offset as usize), buffer.size as usize));
+            if non_coherent && write {
+                device
+                    .flush_mapped_memory_ranges(&[range])
+                    .context(format!("flush {}", buffer.name))?;
+            }
+            Ok(result)
+        })();
+        device.unmap_memory(memory.memory);
+        result
+    }
+
+    /// Copies `data` to the start of `buffer`. Memory the host can't map is written through a staging buffer, and
+    /// the copy is complete and visible to later submissions when this returns.
+    pub unsafe fn write_buffer<T: Copy>(&self, buffer: &Buffer, data: &[T]) -> Result<()> {
+        let size = std::mem::size_of_val(data) as u64;
+        if buffer.memory().flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            let bytes = std::slice::from_raw_parts(data.as_ptr() as *const u8, size as usize);
+            return self.with_mapped(buffer, true, |mapped| mapped[..bytes.len()].copy_from_slice(bytes));
+        }
+        let staging = self.create_buffer(
+            &format!("{}_staging", buffer.name),
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            Placement::HostVisible,
+        )?;
+        self.write_buffer(&staging, data)?;
+        self.execute(&format!("upload {}", buffer.name), |command_buffer| {
+            self.device.cmd_copy_buffer(
+                command_buffer,
+                staging.buffer,
+                buffer.buffer,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size,
+                }],
+            );
+            self.cmd_memory_barrier(
+                command_buffer,
+                (vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE),
+                (vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_READ),
+            );
+        })
+    }
+
+    /// Reads back the whole contents of `buffer`, through a staging buffer if the host can't map its memory. Waits for
+    /// every submission on the active queue to complete first.
+    pub unsafe fn read_buffer(&self, buffer: &Buffer) -> Result<Vec<u8>> {
+        if buffer.memory().flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return self.with_mapped(buffer, false, |mapped| mapped.to_vec());
+        }
+        let staging = self.create_buffer(
+            &format!("{}_readback", buffer.name),
+            buffer.size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            Placement::HostVisible,
+        )?;
+        self.execute(&format!("read back {}", buffer.name), |command_buffer| {
+            self.cmd_memory_barrier(
+                command_buffer,
+                (vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_WRITE),
+                (vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_READ),
+            );
+            self.device.cmd_copy_buffer(
+                command_buffer,
+                buffer.buffer,
+                staging.buffer,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size: buffer.size,
+                }],
+            );
+            self.cmd_memory_barrier(
+                command_buffer,
+                (vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_WRITE),
+                (vk::PipelineStageFlags2::HOST, vk::AccessFlags2::HOST_READ),
+            );
+        })?;
+        self.read_buffer(&staging)
+    }
+
+    /// Records a global memory barrier from the `(stage, access)` pair `src` to `dst`.
+    pub unsafe fn cmd_memory_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+        dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) {
+        self.device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo {
+                memory_barrier_count: 1,
+                p_memory_barriers: &vk::MemoryBarrier2 {
+                    src_stage_mask: src.0,
+                    src_access_mask: src.1,
+                    dst_stage_mask: dst.0,
+                    dst_access_mask: dst.1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+    }
+
+    pub unsafe fn buffer_address(&self, buffer: &Buffer) -> vk::DeviceAddress {
+        self.device.get_buffer_device_address(&vk::BufferDeviceAddressInfo {
+            buffer: buffer.buffer,
+            ..Default::default()
+        })
+    }
+
+    pub unsafe fn create_shader_module(&self, name: &str, code: &[u8]) -> Result<Owned<vk::ShaderModule>> {
+        let module = self
+            .device
+            .create_shader_module(
+                &vk::ShaderModuleCreateInfo {
+                    flags: vk::ShaderModuleCreateFlags::empty(),
+                    code_size: code.len(),
+                    p_code: code.as_ptr() as *const _,
+                    ..Default::default()
+                },
+                None,
+            )
+            .context(format!("create {name} module"))?;
+        let module = Owned::new(&self.device, module);
+        self.names.name(*module, name)?;
+        Ok(module)
+    }
+
+    /// Records the command buffer of the active queue with `record`, inside a region labelled `label`, submits it
+    /// and waits for it to complete. If it doesn't within [`RtContext::timeout`], the device is marked as hung and
+    /// [`Error::Timeout`] is returned.
+    pub unsafe fn execute(&self, label: &str, record: impl FnOnce(vk::CommandBuffer)) -> Result<()> {
+        let device = &self.device;
+        let FamilyQueue {
+            queue,
+            command_buffer,
+            ref fence,
+            ..
+        } = self.queues[self.active_queue];
+        let fence = **fence;
+        device.reset_fences(&[fence]).context(format!("reset {label} fence"))?;
+        device
+            .begin_command_buffer(command_buffer, &Default::default())
+            .context(format!("begin {label}"))?;
+        self.names.begin_label(command_buffer, label);
+        record(command_buffer);
+        self.names.end_label(command_buffer);
+        device
+            .end_command_buffer(command_buffer)
+            .context(format!("record {label}"))?;
+
+        device
+            .queue_submit(
+                queue,
+                &[vk::SubmitInfo {
+                    command_buffer_count: 1,
+                    p_command_buffers: &command_buffer,
+                    ..Default::default()
+                }],
+                fence,
+            )
+            .context(format!("submit {label}"))?;
+        match device.wait_for_fences(&[fence], true, self.timeout.as_nanos() as u64) {
+            Err(vk::Result::TIMEOUT) => {
+                device.mark_hung();
+                Err(Error::Timeout {
+                    operation: label.to_string(),
+                    timeout: self.timeout,
+                    params: None,
+                })
+            }
+            result => result.context(format!("wait for {label}")),
+        }
+    }
+}
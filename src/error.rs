@@ -0,0 +1,157 @@
+//! The error type of the harness. Failing Vulkan calls are reported along with what the harness was doing at the
+//! time, instead of as a bare `vk::Result` from an `unwrap()`.
+
+use std::{fmt, time::Duration};
+
+use ash::{prelude::VkResult, vk};
+
+use crate::{memory::Placement, probe::Skipped};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The system lacks something the tests need. This isn't a failure of the driver under test.
+    Skipped(Skipped),
+    /// The command line asked for something that doesn't exist.
+    Usage(String),
+    /// A Vulkan call returned an error.
+    Vulkan {
+        /// What the harness was doing, e.g. "create blas_backing_buf".
+        operation: String,
+        result: vk::Result,
+        /// Size in bytes of the object being created or allocated, if any.
+        size: Option<u64>,
+    },
+    /// None of the memory types a buffer allows fits its placement, not even the fallbacks.
+    NoMemoryType {
+        operation: String,
+        placement: Placement,
+        /// `VkMemoryRequirements::memoryTypeBits` of the buffer.
+        type_bits: u32,
+    },
+    /// A submission didn't complete within the timeout, most likely because the GPU hung.
+    Timeout {
+        operation: String,
+        timeout: Duration,
+        /// The test parameters of the trace that hung, if it was one.
+        params: Option<String>,
+    },
+    /// A Vulkan call returned `VK_ERROR_DEVICE_LOST`.
+    DeviceLost {
+        operation: String,
+        /// The test parameters of the trace that lost the device, if it was one.
+        params: Option<String>,
+        /// What `VK_EXT_device_fault` had to say about it, if available.
+        fault: Option<String>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether the GPU rather than the harness failed: it hung or lost the device.
+    pub fn gpu_failure(&self) -> bool {
+        matches!(self, Error::Timeout { .. } | Error::DeviceLost { .. })
+    }
+
+    /// Attaches the parameters of the test that was running to GPU failures.
+    pub fn with_params(mut self, test_params: impl fmt::Display) -> Self {
+        if let Error::Timeout { params, .. } | Error::DeviceLost { params, .. } = &mut self {
+            *params = Some(test_params.to_string());
+        }
+        self
+    }
+}
+
+impl From<Skipped> for Error {
+    fn from(skipped: Skipped) -> Self {
+        Error::Skipped(skipped)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Skipped(skipped) => write!(f, "{skipped}"),
+            Error::Usage(message) => write!(f, "{message}"),
+            Error::Vulkan {
+                operation,
+                result,
+                size,
+            } => {
+                write!(f, "{operation} failed with {result}")?;
+                if let Some(size) = size {
+                    write!(f, " ({size} bytes)")?;
+                }
+                Ok(())
+            }
+            Error::NoMemoryType {
+                operation,
+                placement,
+                type_bits,
+            } => write!(
+                f,
+                "{operation} failed: none of the memory types in memoryTypeBits {type_bits:#b} is {placement}"
+            ),
+            Error::Timeout {
+                operation,
+                timeout,
+                params,
+            } => {
+                write!(f, "GPU timeout: {operation} didn't complete within {timeout:?}")?;
+                if let Some(params) = params {
+                    write!(f, " ({params})")?;
+                }
+                Ok(())
+            }
+            Error::DeviceLost {
+                operation,
+                params,
+                fault,
+            } => {
+                write!(f, "device lost during {operation}")?;
+                if let Some(params) = params {
+                    write!(f, " ({params})")?;
+                }
+                if let Some(fault) = fault {
+                    write!(f, "\n{fault}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Attaches the failed operation to the `vk::Result` of a Vulkan call.
+pub trait Context<T> {
+    fn context(self, operation: impl Into<String>) -> Result<T>;
+    /// Like [`Context::context`], for calls that create or allocate `size` bytes.
+    fn context_sized(self, operation: impl Into<String>, size: u64) -> Result<T>;
+}
+
+impl<T> Context<T> for VkResult<T> {
+    fn context(self, operation: impl Into<String>) -> Result<T> {
+        self.map_err(|result| vulkan_error(operation.into(), result, None))
+    }
+
+    fn context_sized(self, operation: impl Into<String>, size: u64) -> Result<T> {
+        self.map_err(|result| vulkan_error(operation.into(), result, Some(size)))
+    }
+}
+
+/// A lost device is reported as such whichever call noticed it.
+fn vulkan_error(operation: String, result: vk::Result, size: Option<u64>) -> Error {
+    if result == vk::Result::ERROR_DEVICE_LOST {
+        return Error::DeviceLost {
+            operation,
+            params: None,
+            fault: None,
+        };
+    }
+    Error::Vulkan {
+        operation,
+        result,
+        size,
+    }
+}
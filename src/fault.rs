@@ -0,0 +1,188 @@
+//! Device-lost diagnostics through `VK_EXT_device_fault`.
+//!
+//! When a bad SBT address kills the device, `vkGetDeviceFaultInfoEXT` may still tell which addresses the GPU choked
+//! on. [`FaultReport`] lines those up with the device address ranges of the buffers the harness knows by name, so that
+//! a fault in `sbt1_buffer` can be told apart from one in padding or in memory nobody allocated.
+
+use std::{ffi::CStr, fmt, ops::Range};
+
+use ash::vk;
+
+use crate::error::{Context, Result};
+
+/// Loaded entry point of `VK_EXT_device_fault`, for a device created with the extension and its `deviceFault`
+/// feature enabled.
+pub struct DeviceFault {
+    device: vk::Device,
+    fp: vk::ExtDeviceFaultFn,
+}
+
+/// A buffer the harness knows by name, and the device addresses it covers.
+pub struct NamedRange {
+    pub name: String,
+    pub range: Range<vk::DeviceAddress>,
+}
+
+/// What `vkGetDeviceFaultInfoEXT` returned, with the named buffers every fault address falls in.
+pub struct FaultReport {
+    pub description: String,
+    pub addresses: Vec<vk::DeviceFaultAddressInfoEXT>,
+    /// `(description, vendorFaultCode, vendorFaultData)` of each vendor-specific fault.
+    pub vendor_infos: Vec<(String, u64, u64)>,
+    pub buffers: Vec<NamedRange>,
+}
+
+impl DeviceFault {
+    pub unsafe fn new(instance: &ash::Instance, device: &ash::Device) -> Self {
+        let fp = vk::ExtDeviceFaultFn::load(|name| {
+            std::mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+        });
+        Self {
+            device: device.handle(),
+            fp,
+        }
+    }
+
+    /// Queries the fault of a lost device. `buffers` are the named buffers to map the fault addresses to. The vendor
+    /// binary crash dump isn't retrieved.
+    pub unsafe fn query(&self, buffers: Vec<NamedRange>) -> Result<FaultReport> {
+        let mut counts = vk::DeviceFaultCountsEXT::default();
+        (self.fp.get_device_fault_info_ext)(self.device, &mut counts, std::ptr::null_mut())
+            .result()
+            .context("get device fault counts")?;
+        let mut addresses = vec![vk::DeviceFaultAddressInfoEXT::default(); counts.address_info_count as usize];
+        let mut vendor_infos = vec![vk::DeviceFaultVendorInfoEXT::default(); counts.vendor_info_count as usize];
+        counts.vendor_binary_size = 0;
+        let mut info = vk::DeviceFaultInfoEXT {
+            p_address_infos: addresses.as_mut_ptr(),
+            p_vendor_infos: vendor_infos.as_mut_ptr(),
+            ..Default::default()
+        };
+        // VK_INCOMPLETE only means that the vendor binary was left out.
+        match (self.fp.get_device_fault_info_ext)(self.device, &mut counts, &mut info) {
+            vk::Result::SUCCESS | vk::Result::INCOMPLETE => {}
+            result => return Err(result).context("get device fault info"),
+        }
+        addresses.truncate(counts.address_info_count as usize);
+        vendor_infos.truncate(counts.vendor_info_count as usize);
+        Ok(FaultReport {
+            description: CStr::from_ptr(info.description.as_ptr()).to_string_lossy().into_owned(),
+            addresses,
+            vendor_infos: vendor_infos
+                .iter()
+                .map(|vendor| {
+                    let description = CStr::from_ptr(vendor.description.as_ptr()).to_string_lossy().into_owned();
+                    (description, vendor.vendor_fault_code, vendor.vendor_fault_data)
+                })
+                .collect(),
+            buffers,
+        })
+    }
+}
+
+/// The addresses a fault may have happened at: `addressPrecision` is a power of two, and the reported address is
+/// only accurate up to it.
+pub fn fault_range(address: &vk::DeviceFaultAddressInfoEXT) -> Range<vk::DeviceAddress> {
+    let precision = address.address_precision.max(1);
+    let start = address.reported_address & !(precision - 1);
+    start..start.saturating_add(precision)
+}
+
+/// The buffers of `buffers` that overlap `range`.
+pub fn overlapping<'a>(buffers: &'a [NamedRange], range: &Range<vk::DeviceAddress>) -> Vec<&'a NamedRange> {
+    buffers
+        .iter()
+        .filter(|buffer| buffer.range.start < range.end && range.start < buffer.range.end)
+        .collect()
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device fault: {}", self.description)?;
+        if self.addresses.is_empty() {
+            write!(f, "\n  no fault addresses reported")?;
+        }
+        for address in &self.addresses {
+            let range = fault_range(address);
+            write!(
+                f,
+                "\n  {:?} at {:#x} (somewhere in {:#x}..{:#x}): ",
+                address.address_type, address.reported_address, range.start, range.end
+            )?;
+            let buffers = overlapping(&self.buffers, &range);
+            if buffers.is_empty() {
+                write!(f, "outside every known buffer")?;
+            }
+            let names = buffers
+                .iter()
+                .map(|buffer| {
+                    if buffer.range.contains(&address.reported_address) {
+                        format!("{} + {:#x}", buffer.name, address.reported_address - buffer.range.start)
+                    } else {
+                        format!("near {}", buffer.name)
+                    }
+                })
+                .collect::<Vec<_>>();
+            write!(f, "{}", names.join(", "))?;
+        }
+        for (description, code, data) in &self.vendor_infos {
+            write!(f, "\n  vendor fault {code:#x} (data {data:#x}): {description}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(reported_address: u64, address_precision: u64) -> vk::DeviceFaultAddressInfoEXT {
+        vk::DeviceFaultAddressInfoEXT {
+            address_type: vk::DeviceFaultAddressTypeEXT::READ_INVALID,
+            reported_address,
+            address_precision,
+        }
+    }
+
+    #[test]
+    fn widens_to_precision() {
+        assert_eq!(fault_range(&address(0x1234, 0x100)), 0x1200..0x1300);
+        assert_eq!(fault_range(&address(0x1234, 1)), 0x1234..0x1235);
+        // A precision of 0 is treated as exact.
+        assert_eq!(fault_range(&address(0x1234, 0)), 0x1234..0x1235);
+    }
+
+    #[test]
+    fn maps_to_buffers() {
+        let buffers = vec![
+            NamedRange {
+                name: "results_buffer".to_string(),
+                range: 0x1000..0x13e8,
+            },
+            NamedRange {
+                name: "sbt1_buffer".to_string(),
+                range: 0x2000..0x23e8,
+            },
+        ];
+        let names = |range| {
+            overlapping(&buffers, &range)
+                .iter()
+                .map(|buffer| buffer.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(0x2040..0x2041), ["sbt1_buffer"]);
+        assert_eq!(names(0x0..0x10000), ["results_buffer", "sbt1_buffer"]);
+        assert_eq!(names(0x13e8..0x2000), Vec::<&str>::new());
+        let report = FaultReport {
+            description: "page fault".to_string(),
+            addresses: vec![address(0x2040, 1), address(0x3000, 0x1000)],
+            vendor_infos: vec![],
+            buffers,
+        };
+        assert_eq!(
+            report.to_string(),
+            "Device fault: page fault\n  READ_INVALID at 0x2040 (somewhere in 0x2040..0x2041): sbt1_buffer + 0x40\n  \
+             READ_INVALID at 0x3000 (somewhere in 0x3000..0x4000): outside every known buffer"
+        );
+    }
+}
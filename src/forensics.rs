@@ -0,0 +1,314 @@
+//! Byte-level analysis of shader record reads that came back wrong.
+//!
+//! A failing trace only tells us that the words a shader copied out of its record differ from the ones we wrote.
+//! [`RecordReport`] lines them up with the bytes actually stored in the SBT buffer and works out where in the buffer
+//! the shader most likely read from, which is the information a driver bug report needs.
+//!
+//! Zeroed padding makes many stray reads look alike. [`fill_sentinels`] instead stores in every word its own byte
+//! offset, tagged with [`SENTINEL_TAG`], so any padding word a shader returns names the exact place it was read from.
+
+use std::{fmt, ops::Range};
+
+use crate::sbt::{SbtLayout, SbtRegion};
+
+/// A named byte range of the SBT buffer.
+pub struct Area {
+    pub name: String,
+    pub range: Range<usize>,
+}
+
+/// Names the handle and payload of every record in `layout`, which was written `base_offset` bytes into the buffer.
+/// Bytes outside these areas are padding.
+pub fn areas(layout: &SbtLayout, base_offset: usize) -> Vec<Area> {
+    let handle_size = layout.handle_size() as usize;
+    let mut areas = Vec::new();
+    for (region, i, range) in layout.records() {
+        let name = format!("{region:?} record {i}").to_lowercase();
+        let start = base_offset + range.start;
+        let handle_end = start + handle_size;
+        areas.push(Area {
+            name: format!("{name} handle"),
+            range: start..handle_end,
+        });
+        if range.len() > handle_size {
+            areas.push(Area {
+                name: format!("{name} data"),
+                range: handle_end..base_offset + range.end,
+            });
+        }
+    }
+    areas
+}
+
+/// Where the payload of the record of `region` at `index` starts in the buffer, if the layout has such a record.
+pub fn data_offset(layout: &SbtLayout, base_offset: usize, region: SbtRegion, index: usize) -> Option<usize> {
+    layout
+        .records()
+        .find(|&(r, i, _)| r == region && i == index)
+        .map(|(_, _, range)| base_offset + range.start + layout.handle_size() as usize)
+}
+
+/// High byte of every sentinel word. The low 24 bits hold the byte offset of the word.
+pub const SENTINEL_TAG: u32 = 0x5E00_0000;
+
+/// Largest buffer [`fill_sentinels`] can fill, since the offsets have to fit in 24 bits.
+pub const MAX_SENTINEL_BYTES: usize = 1 << 24;
+
+/// Fills `bytes` with sentinel words, each holding its own offset into `bytes`.
+pub fn fill_sentinels(bytes: &mut [u8]) {
+    assert!(bytes.len() <= MAX_SENTINEL_BYTES, "sentinel offsets are limited to 24 bits");
+    for (i, word) in bytes.chunks_exact_mut(4).enumerate() {
+        word.copy_from_slice(&(SENTINEL_TAG | (i * 4) as u32).to_ne_bytes());
+    }
+}
+
+/// The offset a sentinel word was stored at, or `None` if `word` isn't a sentinel.
+fn sentinel_offset(word: u32) -> Option<usize> {
+    (word & 0xFF00_0003 == SENTINEL_TAG).then_some((word & 0x00FF_FFFF) as usize)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// Every word matches.
+    None,
+    /// The buffer doesn't hold what we wrote, so the host write is at fault rather than the shader read.
+    StoreMismatch,
+    /// Every word read back is zero.
+    Zeros,
+    /// The whole record was read from `source` bytes into the buffer instead of the intended offset.
+    Shifted { source: usize },
+    /// Only the listed words are wrong.
+    Partial { wrong: Vec<usize> },
+    /// The words don't match anything stored in the buffer.
+    Garbage,
+}
+
+pub struct RecordReport<'a> {
+    pub name: &'a str,
+    /// Byte offset of the record payload in the SBT buffer.
+    pub offset: usize,
+    pub expected: &'a [u32],
+    pub read: &'a [u32],
+    /// The words actually stored at `offset` when the trace completed.
+    pub stored: Vec<u32>,
+    pub corruption: Corruption,
+    sbt: &'a [u8],
+    areas: &'a [Area],
+}
+
+impl<'a> RecordReport<'a> {
+    /// Compares the words a shader copied out of its record (`read`) with the ones written (`expected`) and with
+    /// the contents of the SBT buffer at the time of the trace (`sbt`).
+    pub fn new(
+        name: &'a str,
+        offset: usize,
+        expected: &'a [u32],
+        read: &'a [u32],
+        sbt: &'a [u8],
+        areas: &'a [Area],
+    ) -> Self {
+        let stored = (0..expected.len()).map(|i| word_at(sbt, offset + i * 4)).collect::<Vec<_>>();
+        let corruption = if read == expected {
+            Corruption::None
+        } else if stored != expected {
+            Corruption::StoreMismatch
+        } else if read.iter().all(|&word| word == 0) {
+            Corruption::Zeros
+        } else if let Some(source) = sentinel_source(read).or_else(|| find(sbt, read, offset)) {
+            Corruption::Shifted { source }
+        } else {
+            let wrong = (0..read.len()).filter(|&i| read[i] != expected[i]).collect::<Vec<_>>();
+            if wrong.len() < read.len() {
+                Corruption::Partial { wrong }
+            } else {
+                Corruption::Garbage
+            }
+        };
+        Self {
+            name,
+            offset,
+            expected,
+            read,
+            stored,
+            corruption,
+            sbt,
+            areas,
+        }
+    }
+
+    /// Name of the area `offset` falls into.
+    fn area(&self, offset: usize) -> &str {
+        self.areas
+            .iter()
+            .find(|area| area.range.contains(&offset))
+            .map_or("padding", |area| area.name.as_str())
+    }
+
+    /// One-line classification of the corruption.
+    pub fn verdict(&self) -> String {
+        match &self.corruption {
+            Corruption::None => "record read correctly".to_string(),
+            Corruption::StoreMismatch => "the SBT buffer doesn't hold the data that was written".to_string(),
+            Corruption::Zeros => "all words read back as zero".to_string(),
+            Corruption::Shifted { source } => {
+                let delta = *source as i64 - self.offset as i64;
+                format!(
+                    "record read came from offset {source} ({}) instead of {}, {} bytes {}",
+                    self.area(*source),
+                    self.offset,
+                    delta.abs(),
+                    if delta < 0 { "before" } else { "after" }
+                )
+            }
+            Corruption::Partial { wrong } => {
+                let mut verdict = format!("{} of {} words wrong", wrong.len(), self.read.len());
+                for &i in wrong {
+                    if let Some(source) = sentinel_offset(self.read[i]) {
+                        let intended = self.offset + i * 4;
+                        verdict += &format!(
+                            ", word {i} came from offset {source} ({}) instead of {intended}",
+                            self.area(source)
+                        );
+                    }
+                }
+                verdict
+            }
+            Corruption::Garbage => "words don't match anything stored in the SBT buffer".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RecordReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} record: payload expected at SBT offset {}", self.name, self.offset)?;
+        writeln!(f, "{:>6}  {:>10}  {:>10}  {:>10}", "word", "expected", "stored", "read")?;
+        for (i, (&expected, &read)) in self.expected.iter().zip(self.read).enumerate() {
+            write!(f, "{i:>6}  {expected:#010x}  {:#010x}  {read:#010x}", self.stored[i])?;
+            if read != expected {
+                // Point at the sentinel's offset, or else at the copy of this word closest to where it should have come
+                // from.
+                let near = self.offset + i * 4;
+                match sentinel_offset(read).or_else(|| find(self.sbt, &[read], near)) {
+                    Some(source) if read != 0 => {
+                        write!(f, "  <- SBT offset {source} ({})", self.area(source))?
+                    }
+                    _ => write!(f, "  <- wrong")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        write!(f, "verdict: {}", self.verdict())
+    }
+}
+
+fn word_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..offset + 4)
+        .map_or(0, |word| u32::from_ne_bytes(word.try_into().unwrap()))
+}
+
+/// Where `words` were read from if they start with, or are all, sentinels that agree on a single source offset.
+fn sentinel_source(words: &[u32]) -> Option<usize> {
+    let mut sources = words
+        .iter()
+        .enumerate()
+        .map(|(i, &word)| sentinel_offset(word)?.checked_sub(i * 4));
+    let source = sources.next()??;
+    sources.all(|other| other == Some(source)).then_some(source)
+}
+
+/// The 4-byte aligned offset of `words` in `bytes` closest to `near`, other than `near` itself.
+fn find(bytes: &[u8], words: &[u32], near: usize) -> Option<usize> {
+    let len = words.len() * 4;
+    (0..bytes.len().saturating_sub(len) + 1)
+        .step_by(4)
+        .filter(|&offset| offset != near)
+        .filter(|&offset| (0..words.len()).all(|i| word_at(bytes, offset + i * 4) == words[i]))
+        .min_by_key(|&offset| offset.abs_diff(near))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPECTED: [u32; 4] = [1, 2, 3, 4];
+
+    fn words_as_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_ne_bytes()).collect()
+    }
+
+    /// A 32 byte handle of 0xAA bytes at offset 32, followed by `EXPECTED` at offset 64.
+    fn sbt() -> Vec<u8> {
+        let mut sbt = vec![0; 128];
+        sbt[32..64].fill(0xAA);
+        sbt[64..80].copy_from_slice(&words_as_bytes(&EXPECTED));
+        sbt
+    }
+
+    fn areas() -> Vec<Area> {
+        vec![
+            Area {
+                name: "raygen record 0 handle".to_string(),
+                range: 32..64,
+            },
+            Area {
+                name: "raygen record 0 data".to_string(),
+                range: 64..80,
+            },
+        ]
+    }
+
+    fn corruption(read: &[u32], sbt: &[u8]) -> Corruption {
+        RecordReport::new("raygen", 64, &EXPECTED, read, sbt, &areas()).corruption
+    }
+
+    #[test]
+    fn classifies() {
+        let sbt = sbt();
+        assert_eq!(corruption(&EXPECTED, &sbt), Corruption::None);
+        assert_eq!(corruption(&[0; 4], &sbt), Corruption::Zeros);
+        assert_eq!(corruption(&[1, 2, 9, 4], &sbt), Corruption::Partial { wrong: vec![2] });
+        assert_eq!(corruption(&[9; 4], &sbt), Corruption::Garbage);
+        assert_eq!(corruption(&[9; 4], &[0; 128]), Corruption::StoreMismatch);
+    }
+
+    #[test]
+    fn finds_shifted_reads() {
+        let sbt = sbt();
+        let areas = areas();
+        let handle = [0xAAAAAAAA; 4];
+        let report = RecordReport::new("raygen", 64, &EXPECTED, &handle, &sbt, &areas);
+        assert_eq!(report.corruption, Corruption::Shifted { source: 48 });
+        assert_eq!(
+            report.verdict(),
+            "record read came from offset 48 (raygen record 0 handle) instead of 64, 16 bytes before"
+        );
+
+        let tail = [0xAAAAAAAA, 1, 2, 3];
+        let report = RecordReport::new("raygen", 64, &EXPECTED, &tail, &sbt, &areas);
+        assert_eq!(report.corruption, Corruption::Shifted { source: 60 });
+    }
+
+    #[test]
+    fn decodes_sentinels() {
+        let mut sbt = vec![0; 128];
+        fill_sentinels(&mut sbt);
+        sbt[64..80].copy_from_slice(&words_as_bytes(&EXPECTED));
+        let areas = areas();
+
+        let padding = [SENTINEL_TAG | 80, SENTINEL_TAG | 84, SENTINEL_TAG | 88, SENTINEL_TAG | 92];
+        let report = RecordReport::new("raygen", 64, &EXPECTED, &padding, &sbt, &areas);
+        assert_eq!(report.corruption, Corruption::Shifted { source: 80 });
+        assert_eq!(
+            report.verdict(),
+            "record read came from offset 80 (padding) instead of 64, 16 bytes after"
+        );
+
+        let torn = [1, 2, SENTINEL_TAG | 16, 4];
+        let report = RecordReport::new("raygen", 64, &EXPECTED, &torn, &sbt, &areas);
+        assert_eq!(
+            report.verdict(),
+            "1 of 4 words wrong, word 2 came from offset 16 (padding) instead of 72"
+        );
+    }
+}
@@ -0,0 +1,596 @@
+//! The SBT alignment repro: the acceleration structures, pipeline and results buffer of the test shaders, traced with
+//! many SBT layouts through [`Harness::trace`].
+
+use std::{fmt, str::FromStr};
+
+use ash::vk;
+
+use crate::{
+    accel,
+    context::RtContext,
+    error::{Error, Result},
+    fault::NamedRange,
+    forensics::{self, Corruption, RecordReport},
+    memory::Placement,
+    owned::{AccelerationStructure, Buffer},
+    pipeline::RtPipeline,
+    sbt::{SbtBuilder, SbtLayout, SbtRegion},
+};
+
+/// Payload of the raygen record, copied by the raygen shader into the first 16 words of the results buffer.
+pub const RAYGEN_RECORD_DATA: [u32; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+/// Payload of the miss record, copied by the miss shader.
+pub const MISS_RECORD_DATA: [u32; 4] = [100, 101, 102, 103];
+/// Payload of the hit group record, copied by both the intersection and the closest hit shader.
+pub const HIT_RECORD_DATA: [u32; 4] = [200, 201, 202, 203];
+
+// SBT Layout, with region offsets aligned to shaderGroupBaseAlignment:
+// |             |      Raygen          |         |       Raymiss      |         |      Hitgroup      |
+// | base offset |32|-SBT Data 64 bytes-| padding |32|-SBT Data 16 B-| padding |32|-SBT Data 16 B-|
+//                    ^^^ Incorrect read here
+
+// Results buffer layout, in u32 words:
+// | 0..16 raygen record | 16 closest hit ran | 17 intersection ran | 18 miss ran | 19 unused |
+// | 20..24 miss record | 24..28 hit group record, as read by intersection | 28..32 same, as read by closest hit |
+
+/// Number of u32 words of the results buffer inspected after a trace.
+pub const RESULT_WORDS: usize = 32;
+pub const RESULTS_SIZE: u64 = 1000;
+
+pub fn words_as_bytes(words: &[u32]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, std::mem::size_of_val(words)) }
+}
+
+/// An SBT written by [`Harness::prepare_trace`], ready to be traced with [`Harness::cmd_trace`].
+pub struct PreparedTrace {
+    pub params: TraceParams,
+    pub layout: SbtLayout,
+    /// Device address the SBT starts at, see [`TraceResult::sbt_address`].
+    pub sbt_address: vk::DeviceAddress,
+    /// The raygen, miss, hit and callable regions to trace with.
+    pub regions: [vk::StridedDeviceAddressRegionKHR; 4],
+}
+
+/// Words read back from the results buffer after one `cmd_trace_rays`, along with what the SBT looked like.
+pub struct TraceResult {
+    pub words: [u32; RESULT_WORDS],
+    pub params: TraceParams,
+    pub layout: SbtLayout,
+    /// Contents of `sbt1_buffer` once the trace completed.
+    pub sbt: Vec<u8>,
+    /// Device address the SBT starts at, `params.location.buffer_offset()` bytes into `sbt1_buffer`. The raygen
+    /// region is `params.base_offset` bytes after it.
+    pub sbt_address: vk::DeviceAddress,
+}
+
+// Each stage copies its shader record into the results buffer. A stage passes if it ran and the record data it read
+// is the same as the one we put in.
+impl TraceResult {
+    pub fn raygen_ok(&self) -> bool {
+        self.words[..16] == RAYGEN_RECORD_DATA
+    }
+    pub fn intersection_ok(&self) -> bool {
+        self.words[17] == 12777 && self.words[24..28] == HIT_RECORD_DATA
+    }
+    pub fn closest_hit_ok(&self) -> bool {
+        self.words[16] == 120000 && self.words[28..32] == HIT_RECORD_DATA
+    }
+    pub fn miss_ok(&self) -> bool {
+        self.words[18] == 125 && self.words[20..24] == MISS_RECORD_DATA
+    }
+    pub fn passed(&self) -> bool {
+        self.raygen_ok() && self.intersection_ok() && self.closest_hit_ok() && self.miss_ok()
+    }
+
+    /// Names of the stages that didn't pass, in pipeline order.
+    pub fn failed_stages(&self) -> Vec<&'static str> {
+        [
+            ("raygen", self.raygen_ok()),
+            ("intersection", self.intersection_ok()),
+            ("closest hit", self.closest_hit_ok()),
+            ("miss", self.miss_ok()),
+        ]
+        .into_iter()
+        .filter(|(_, ok)| !ok)
+        .map(|(stage, _)| stage)
+        .collect()
+    }
+
+    /// Calls `f` with a byte-level report for every shader record that was read back wrong.
+    pub fn for_each_corruption(&self, mut f: impl FnMut(&RecordReport)) {
+        // `sbt` holds all of `sbt1_buffer`, and the layout starts `base_offset` bytes after the SBT itself.
+        let layout_offset = self.params.location.buffer_offset() as usize + self.params.base_offset;
+        let areas = forensics::areas(&self.layout, layout_offset);
+        let record_index = self.params.record_index as usize;
+        for (stage, region, index, expected, read) in [
+            ("raygen", SbtRegion::Raygen, 0, &RAYGEN_RECORD_DATA[..], &self.words[..16]),
+            ("miss", SbtRegion::Miss, record_index, &MISS_RECORD_DATA, &self.words[20..24]),
+            ("intersection", SbtRegion::Hit, record_index, &HIT_RECORD_DATA, &self.words[24..28]),
+            ("closest hit", SbtRegion::Hit, record_index, &HIT_RECORD_DATA, &self.words[28..32]),
+        ] {
+            let offset = forensics::data_offset(&self.layout, layout_offset, region, index).unwrap();
+            let report = RecordReport::new(stage, offset, expected, read, &self.sbt, &areas);
+            if report.corruption != Corruption::None {
+                f(&report);
+            }
+        }
+    }
+}
+
+/// SBT placement for one [`Harness::trace`].
+#[derive(Clone, Copy, Default)]
+pub struct TraceParams {
+    /// Bytes between the start of `sbt1_buffer` and the raygen region.
+    pub base_offset: usize,
+    /// Stride overrides for the raygen, miss and hit regions. `None` uses the smallest stride that fits.
+    pub strides: [Option<u64>; 3],
+    /// Index of the miss and hit group records the rays use. The records before it have null handles, so a driver
+    /// that reads one of them instead of the intended record skips the shader.
+    pub record_index: u32,
+    /// Memory `sbt1_buffer` is allocated from.
+    pub placement: Placement,
+    /// Where the SBT lives in `sbt1_buffer` and where the buffer lives in its memory.
+    pub location: SbtLocation,
+}
+
+impl TraceParams {
+    pub fn at_offset(base_offset: usize) -> Self {
+        Self {
+            base_offset,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for TraceParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let strides = self
+            .strides
+            .map(|stride| stride.map_or("default".to_string(), |stride| stride.to_string()));
+        write!(
+            f,
+            "base offset {}, raygen/miss/hit strides {}, record index {}, SBT in {} memory at {}",
+            self.base_offset,
+            strides.join("/"),
+            self.record_index,
+            self.placement,
+            self.location
+        )
+    }
+}
+
+/// Where the SBT starts, before `base_offset` moves the raygen region further in. The variants put the SBT at
+/// different device addresses without changing the offsets between its regions, which tells apart bugs that depend
+/// on the absolute address from bugs that depend on the region offsets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SbtLocation {
+    /// At the start of `sbt1_buffer`, which is at the start of its memory unless it is sub-allocated.
+    #[default]
+    Start,
+    /// This many bytes into a larger `sbt1_buffer`, as if the SBT shared a buffer with other data. Must be a multiple
+    /// of `shaderGroupBaseAlignment`.
+    InBuffer(u64),
+    /// At the start of `sbt1_buffer`, bound at least this many bytes into its memory.
+    AtMemoryOffset(u64),
+}
+
+impl SbtLocation {
+    /// The three kinds of location, the non-zero ones at `offset`.
+    pub fn variants(offset: u64) -> [SbtLocation; 3] {
+        [SbtLocation::Start, SbtLocation::InBuffer(offset), SbtLocation::AtMemoryOffset(offset)]
+    }
+
+    /// Offset of the SBT in `sbt1_buffer`.
+    pub fn buffer_offset(self) -> u64 {
+        match self {
+            SbtLocation::InBuffer(offset) => offset,
+            SbtLocation::Start | SbtLocation::AtMemoryOffset(_) => 0,
+        }
+    }
+
+    /// Smallest offset at which `sbt1_buffer` is bound to its memory.
+    pub fn memory_offset(self) -> u64 {
+        match self {
+            SbtLocation::AtMemoryOffset(offset) => offset,
+            SbtLocation::Start | SbtLocation::InBuffer(_) => 0,
+        }
+    }
+}
+
+impl fmt::Display for SbtLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SbtLocation::Start => write!(f, "start"),
+            SbtLocation::InBuffer(offset) => write!(f, "buffer:{offset}"),
+            SbtLocation::AtMemoryOffset(offset) => write!(f, "memory:{offset}"),
+        }
+    }
+}
+
+impl FromStr for SbtLocation {
+    type Err = String;
+
+    fn from_str(location: &str) -> std::result::Result<Self, String> {
+        let error = || format!("unknown SBT location {location:?}, expected start, buffer:<bytes> or memory:<bytes>");
+        if location == "start" {
+            return Ok(SbtLocation::Start);
+        }
+        let (kind, offset) = location.split_once(':').ok_or_else(error)?;
+        let offset = offset.parse().map_err(|_| error())?;
+        match kind {
+            "buffer" => Ok(SbtLocation::InBuffer(offset)),
+            "memory" => Ok(SbtLocation::AtMemoryOffset(offset)),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Outcome of [`Harness::detect_base_alignment`].
+pub struct AlignmentReport {
+    /// `shaderGroupBaseAlignment` as declared by the driver.
+    pub declared: u32,
+    /// Smallest power-of-two multiple of `shaderGroupHandleAlignment` at which the raygen record read back
+    /// correctly at every tested offset, or `None` if no tested alignment worked.
+    pub effective: Option<u32>,
+}
+
+impl AlignmentReport {
+    /// True if the declared alignment isn't enough for the raygen record to be read back correctly.
+    /// An effective alignment smaller than the declared one is fine: the driver is merely conservative.
+    pub fn mismatch(&self) -> bool {
+        self.effective.is_none_or(|effective| effective > self.declared)
+    }
+
+    /// The alignment an application should use for SBT regions on this device, if any works.
+    pub fn safe_alignment(&self) -> Option<u32> {
+        self.effective.map(|effective| effective.max(self.declared))
+    }
+}
+
+impl fmt::Display for AlignmentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "declared shaderGroupBaseAlignment: {}", self.declared)?;
+        match self.effective {
+            Some(effective) => writeln!(f, "effective shaderGroupBaseAlignment: {effective}")?,
+            None => writeln!(f, "effective shaderGroupBaseAlignment: none of the tested alignments worked")?,
+        }
+        match (self.mismatch(), self.safe_alignment()) {
+            (false, _) => write!(f, "OK: the declared alignment is sufficient"),
+            (true, Some(safe)) => write!(f, "MISMATCH: the driver under-reports the alignment, use {safe} instead"),
+            (true, None) => write!(f, "MISMATCH: the raygen record could not be read back at any tested alignment"),
+        }
+    }
+}
+
+/// Everything `cmd_trace_rays` needs, built once so that the same pipeline can be traced with many SBT layouts.
+///
+/// Fields are dropped in declaration order, which destroys the objects in a valid order: the descriptor pool before
+/// the acceleration structure its set refers to, the TLAS before the BLAS it instances, and everything before the
+/// device.
+pub struct Harness {
+    pub pipeline: RtPipeline,
+    tlas: AccelerationStructure,
+    blas: AccelerationStructure,
+    pub results_buffer: Buffer,
+    /// Created by [`Harness::reserve_sbt`] on the first trace.
+    pub sbt1: Option<Buffer>,
+    /// Smallest memory offset `sbt1` was bound at, see [`SbtLocation::memory_offset`].
+    sbt1_memory_offset: u64,
+    /// Fill the SBT buffer with sentinel words instead of zeros before writing the records.
+    pub poison: bool,
+    pub ctx: RtContext,
+}
+
+impl Harness {
+    /// Builds the acceleration structures, results buffer and pipeline on `ctx`.
+    pub unsafe fn new(ctx: RtContext) -> Result<Self> {
+        let aabbs = [vk::AabbPositionsKHR {
+            min_x: 0.0,
+            min_y: 0.0,
+            min_z: 0.0,
+            max_x: 1.0,
+            max_y: 1.0,
+            max_z: 1.0,
+        }];
+        let blas = accel::build_aabb_blas(&ctx, &aabbs)?;
+        let tlas = accel::build_tlas(&ctx, &blas)?;
+        let results_buffer = ctx.create_buffer(
+            "results_buffer",
+            RESULTS_SIZE,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            Placement::HostVisible,
+        )?;
+        let pipeline = RtPipeline::new(&ctx, &tlas, &results_buffer)?;
+        Ok(Self {
+            pipeline,
+            tlas,
+            blas,
+            results_buffer,
+            sbt1: None,
+            sbt1_memory_offset: 0,
+            poison: false,
+            ctx,
+        })
+    }
+
+    /// The buffers the traces read from or write to, for reporting where they ended up in memory.
+    pub fn buffers(&self) -> Vec<&Buffer> {
+        let mut buffers = vec![&self.blas.buffer, &self.tlas.buffer, &self.results_buffer];
+        buffers.extend(&self.sbt1);
+        buffers
+    }
+
+    /// Makes sure `sbt1_buffer` holds an SBT of at least `size` bytes at `location`, with `placement`, replacing it
+    /// if needed.
+    pub unsafe fn reserve_sbt(&mut self, size: u64, placement: Placement, location: SbtLocation) -> Result<()> {
+        // Keep the original 1000 byte buffer for the default layout.
+        let size = location.buffer_offset() + size.max(1000);
+        if self.sbt1_memory_offset == location.memory_offset()
+            && self
+                .sbt1
+                .as_ref()
+                .is_some_and(|sbt1| sbt1.size >= size && sbt1.placement == placement)
+        {
+            return Ok(());
+        }
+        self.sbt1 = None;
+        self.sbt1 = Some(self.ctx.create_buffer_at(
+            "sbt1_buffer",
+            size,
+            vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            placement,
+            location.memory_offset(),
+            1,
+        )?);
+        self.sbt1_memory_offset = location.memory_offset();
+        Ok(())
+    }
+
+    /// Makes `buffer`, which must have been created like [`Harness::reserve_sbt`] creates `sbt1_buffer`, the SBT
+    /// buffer of the following traces at [`SbtLocation::Start`] or [`SbtLocation::InBuffer`] locations that fit.
+    pub fn adopt_sbt(&mut self, buffer: Buffer) {
+        self.sbt1 = Some(buffer);
+        self.sbt1_memory_offset = 0;
+    }
+
+    /// Writes the SBT as described by `params` into `sbt1_buffer`, traces a ray that hits and one that misses, and
+    /// reads back the results. GPU timeouts and device losses name `params`, and device losses carry the fault
+    /// info of `VK_EXT_device_fault` if the device has it.
+    pub unsafe fn trace(&mut self, params: TraceParams) -> Result<TraceResult> {
+        self.write_and_trace(params).map_err(|err| self.explain_fault(err.with_params(params)))
+    }
+
+    /// Adds the device fault report to `err` if it is a device loss.
+    pub unsafe fn explain_fault(&self, mut err: Error) -> Error {
+        if let (Error::DeviceLost { fault, .. }, Some(device_fault)) = (&mut err, &self.ctx.device_fault) {
+            let buffers = self
+                .buffers()
+                .into_iter()
+                .map(|buffer| {
+                    let address = self.ctx.buffer_address(buffer);
+                    NamedRange {
+                        name: buffer.name.clone(),
+                        range: address..address + buffer.size,
+                    }
+                })
+                .collect();
+            *fault = Some(match device_fault.query(buffers) {
+                Ok(report) => report.to_string(),
+                Err(query_err) => format!("no device fault info: {query_err}"),
+            });
+        }
+        err
+    }
+
+    unsafe fn write_and_trace(&mut self, params: TraceParams) -> Result<TraceResult> {
+        let prepared = self.prepare_trace(params)?;
+        self.ctx.execute("trace rays", |command_buffer| self.cmd_trace(command_buffer, &prepared))?;
+        self.collect_trace(prepared)
+    }
+
+    /// The first step of [`Harness::trace`]: writes the SBT as described by `params` into `sbt1_buffer` and clears
+    /// the results buffer.
+    pub unsafe fn prepare_trace(&mut self, params: TraceParams) -> Result<PreparedTrace> {
+        let base_offset = params.base_offset;
+        let mut builder = SbtBuilder::new(&self.ctx.rtx_pipeline_properties, &self.pipeline.group_handles);
+        for (region, stride) in [SbtRegion::Raygen, SbtRegion::Miss, SbtRegion::Hit].into_iter().zip(params.strides) {
+            if let Some(stride) = stride {
+                builder = builder.stride(region, stride);
+            }
+        }
+        for _ in 0..params.record_index {
+            builder = builder.push_null(SbtRegion::Miss).push_null(SbtRegion::Hit);
+        }
+        let layout = builder
+            .push(SbtRegion::Raygen, 0, words_as_bytes(&RAYGEN_RECORD_DATA))
+            .push(SbtRegion::Miss, 1, words_as_bytes(&MISS_RECORD_DATA))
+            .push(SbtRegion::Hit, 2, words_as_bytes(&HIT_RECORD_DATA))
+            .build();
+        let buffer_offset = params.location.buffer_offset();
+        let base_alignment = self.ctx.rtx_pipeline_properties.shader_group_base_alignment as u64;
+        if !buffer_offset.is_multiple_of(base_alignment) {
+            return Err(Error::Usage(format!(
+                "the SBT must start at a multiple of shaderGroupBaseAlignment ({base_alignment}), not at {}",
+                params.location
+            )));
+        }
+        let sbt_end = buffer_offset + base_offset as u64 + layout.size();
+        if self.poison && sbt_end > forensics::MAX_SENTINEL_BYTES as u64 {
+            return Err(Error::Usage(format!(
+                "--poison needs the SBT within the first {} MiB of its buffer, at {} it ends at byte {sbt_end}",
+                forensics::MAX_SENTINEL_BYTES >> 20,
+                params.location
+            )));
+        }
+        self.reserve_sbt(base_offset as u64 + layout.size(), params.placement, params.location)?;
+        let sbt1 = self.sbt1.as_ref().unwrap();
+        self.ctx.write_buffer(&self.results_buffer, &[0u8; RESULTS_SIZE as usize])?;
+        let mut sbt = vec![0; sbt1.size as usize];
+        // Null records write their own zero handles, so poisoning the rest of the buffer doesn't affect them. A buffer
+        // kept from an earlier trace may be larger than the sentinels can cover, but the SBT is within their reach.
+        if self.poison {
+            let poisoned = sbt.len().min(forensics::MAX_SENTINEL_BYTES);
+            forensics::fill_sentinels(&mut sbt[..poisoned]);
+        }
+        layout.write(&mut sbt[buffer_offset as usize + base_offset..]);
+        self.ctx.write_buffer(sbt1, &sbt)?;
+
+        let base_address = self.ctx.buffer_address(sbt1) + buffer_offset;
+        assert!(base_address.is_multiple_of(base_alignment));
+        let regions = layout.device_regions(base_address + base_offset as u64);
+        Ok(PreparedTrace {
+            params,
+            layout,
+            sbt_address: base_address,
+            regions,
+        })
+    }
+
+    /// The second step of [`Harness::trace`]: records the trace of `prepared` into `command_buffer`, followed by a
+    /// barrier that makes the results available to the host.
+    pub unsafe fn cmd_trace(&self, command_buffer: vk::CommandBuffer, prepared: &PreparedTrace) {
+        self.pipeline
+            .cmd_trace(&self.ctx, command_buffer, &prepared.regions, prepared.params.record_index);
+        self.ctx.cmd_memory_barrier(
+            command_buffer,
+            (vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::MEMORY_WRITE),
+            (vk::PipelineStageFlags2::HOST, vk::AccessFlags2::HOST_READ),
+        );
+    }
+
+    /// The last step of [`Harness::trace`], once the trace has completed: reads back the results and the SBT.
+    pub unsafe fn collect_trace(&self, prepared: PreparedTrace) -> Result<TraceResult> {
+        let results = self.ctx.read_buffer(&self.results_buffer)?;
+        let mut words = [0; RESULT_WORDS];
+        for (word, bytes) in words.iter_mut().zip(results.chunks_exact(4)) {
+            *word = u32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+        let sbt = self.ctx.read_buffer(self.sbt1.as_ref().unwrap())?;
+        Ok(TraceResult {
+            words,
+            params: prepared.params,
+            layout: prepared.layout,
+            sbt,
+            sbt_address: prepared.sbt_address,
+        })
+    }
+
+    /// Finds the smallest base alignment at which the raygen shader record reads back correctly.
+    ///
+    /// Candidates are `shaderGroupHandleAlignment` and its power-of-two multiples up to `max_alignment`. A
+    /// candidate is accepted only if the raygen record reads back correctly at every multiple of it in
+    /// `0..=max_alignment`, since a driver may get lucky at one offset and still be wrong at the next.
+    pub unsafe fn detect_base_alignment(
+        &mut self,
+        max_alignment: usize,
+        placement: Placement,
+        location: SbtLocation,
+    ) -> Result<AlignmentReport> {
+        let handle_alignment = self.ctx.rtx_pipeline_properties.shader_group_handle_alignment as usize;
+        let mut raygen_ok_at = std::collections::HashMap::new();
+        let mut effective = None;
+        let mut alignment = handle_alignment;
+        while alignment <= max_alignment {
+            let mut works = true;
+            for offset in (0..=max_alignment).step_by(alignment) {
+                let raygen_ok = match raygen_ok_at.get(&offset) {
+                    Some(&raygen_ok) => raygen_ok,
+                    None => self
+                        .trace(TraceParams {
+                            placement,
+                            location,
+                            ..TraceParams::at_offset(offset)
+                        })?
+                        .raygen_ok(),
+                };
+                raygen_ok_at.insert(offset, raygen_ok);
+                if !raygen_ok {
+                    works = false;
+                    break;
+                }
+            }
+            if works {
+                effective = Some(alignment as u32);
+                break;
+            }
+            alignment *= 2;
+        }
+        Ok(AlignmentReport {
+            declared: self.ctx.rtx_pipeline_properties.shader_group_base_alignment,
+            effective,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_locations() {
+        for location in SbtLocation::variants(4096) {
+            assert_eq!(location.to_string().parse(), Ok(location));
+        }
+        assert_eq!("buffer:64".parse(), Ok(SbtLocation::InBuffer(64)));
+        assert!("buffer".parse::<SbtLocation>().is_err());
+        assert!("memory:-1".parse::<SbtLocation>().is_err());
+        assert!("heap:64".parse::<SbtLocation>().is_err());
+    }
+
+    #[test]
+    fn reports_corruption_inside_a_larger_buffer() {
+        let properties = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR {
+            shader_group_handle_size: 32,
+            shader_group_handle_alignment: 32,
+            shader_group_base_alignment: 64,
+            max_shader_group_stride: 4096,
+            ..Default::default()
+        };
+        let handles = (0..3).flat_map(|group| [group as u8 + 1; 32]).collect::<Vec<_>>();
+        let layout = SbtBuilder::new(&properties, &handles)
+            .push(SbtRegion::Raygen, 0, words_as_bytes(&RAYGEN_RECORD_DATA))
+            .push(SbtRegion::Miss, 1, words_as_bytes(&MISS_RECORD_DATA))
+            .push(SbtRegion::Hit, 2, words_as_bytes(&HIT_RECORD_DATA))
+            .build();
+        let params = TraceParams {
+            location: SbtLocation::InBuffer(4096),
+            ..TraceParams::at_offset(64)
+        };
+        let mut sbt = vec![0; 8192];
+        layout.write(&mut sbt[4096 + 64..]);
+
+        // The raygen shader read its record 16 bytes early, the miss shader read zeros and the rest passed.
+        let raygen_data = 4096 + 64 + 32;
+        let mut words = [0; RESULT_WORDS];
+        for (word, bytes) in words[..16].iter_mut().zip(sbt[raygen_data - 16..].chunks_exact(4)) {
+            *word = u32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+        words[16] = 120000;
+        words[17] = 12777;
+        words[18] = 125;
+        words[24..28].copy_from_slice(&HIT_RECORD_DATA);
+        words[28..32].copy_from_slice(&HIT_RECORD_DATA);
+        let result = TraceResult {
+            words,
+            params,
+            layout,
+            sbt,
+            sbt_address: 0,
+        };
+        assert_eq!(result.failed_stages(), ["raygen", "miss"]);
+
+        let mut reports = Vec::new();
+        result.for_each_corruption(|report| reports.push((report.name.to_string(), report.offset, report.verdict())));
+        assert_eq!(reports.len(), 2);
+        assert_eq!(
+            reports[0],
+            (
+                "raygen".to_string(),
+                raygen_data,
+                "record read came from offset 4176 (raygen record 0 handle) instead of 4192, 16 bytes before".to_string()
+            )
+        );
+        assert_eq!(reports[1].0, "miss");
+        assert!(reports[1].2.contains("zero"), "{}", reports[1].2);
+    }
+}
@@ -0,0 +1,170 @@
+//! High device addresses. Padding buffers push `sbt1_buffer` past 4 GiB of device address space, to catch drivers
+//! that truncate SBT addresses to 32 bits or carry wrongly when adding record offsets to them.
+//!
+//! Vulkan doesn't let the application choose device addresses, but drivers tend to hand them out in allocation
+//! order. The padding is sized from the addresses actually observed, so that the SBT buffer ends up spanning the
+//! 4 GiB boundary, and the traces report the addresses they really used.
+
+use ash::vk;
+
+use crate::{
+    error::{Error, Result},
+    harness::{Harness, SbtLocation},
+    memory::Placement,
+    owned::Buffer,
+    probe::Skipped,
+    sbt::align_up,
+};
+
+pub const FOUR_GIB: u64 = 1 << 32;
+
+/// Largest padding buffer, well below `maxMemoryAllocationSize` on every driver.
+const PADDING_SIZE: u64 = 256 << 20;
+
+/// Size of `sbt1_buffer` in high-address mode, large enough to be likely to span the boundary.
+const SBT_BUFFER_SIZE: u64 = 1 << 20;
+
+/// Room left for the SBT after each location, for its records and the base offset.
+const SBT_ROOM: u64 = 64 << 10;
+
+/// Where the SBT of a high-address `sbt1_buffer` can go.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HighLocations {
+    /// The raygen record starts `shaderGroupBaseAlignment` bytes below a multiple of 4 GiB, so that it straddles it.
+    pub straddling: Option<SbtLocation>,
+    /// The whole SBT is above 4 GiB.
+    pub above: Option<SbtLocation>,
+}
+
+impl HighLocations {
+    /// The locations in a buffer of `size` bytes at device address `address`, a multiple of `base_alignment`.
+    pub fn new(address: u64, size: u64, base_alignment: u64) -> Self {
+        let fits = |offset: u64| offset + SBT_ROOM <= size;
+        let boundary = align_up(address, FOUR_GIB);
+        let straddling = (boundary - address)
+            .checked_sub(base_alignment)
+            .filter(|&offset| fits(offset))
+            .map(SbtLocation::InBuffer);
+        let above = Some(align_up(FOUR_GIB.saturating_sub(address), base_alignment))
+            .filter(|&offset| fits(offset))
+            .map(SbtLocation::InBuffer);
+        Self { straddling, above }
+    }
+}
+
+/// Padding that keeps `sbt1_buffer` at a high address for as long as it's alive.
+pub struct HighAddress {
+    _padding: Vec<Buffer>,
+    /// Total size of the padding buffers.
+    pub padding_size: u64,
+    /// Device address of `sbt1_buffer`.
+    pub sbt_address: vk::DeviceAddress,
+    pub locations: HighLocations,
+}
+
+/// Allocates padding buffers with `placement` until a new `sbt1_buffer` spans or is above 4 GiB of device address
+/// space, and makes it the SBT buffer of `harness`. No padding is needed if the driver hands out high addresses to
+/// begin with. Otherwise, skips if the heap of the SBT's memory type is too small, or the padding runs out of memory
+/// before the addresses get there.
+pub unsafe fn place_sbt_high(harness: &mut Harness, placement: Placement) -> Result<HighAddress> {
+    harness.sbt1 = None;
+    let ctx = &harness.ctx;
+    let create_sbt = || {
+        ctx.create_buffer(
+            "sbt1_buffer",
+            SBT_BUFFER_SIZE,
+            vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            placement,
+        )
+    };
+    let out_of_memory = |err: Error, padding_size: u64| match err {
+        Error::Vulkan {
+            result: vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY,
+            ..
+        } => Skipped::missing(&format!(
+            "memory for device addresses above 4 GiB, ran out after {} MiB of padding",
+            padding_size >> 20
+        ))
+        .into(),
+        err => err,
+    };
+    let mut padding = Vec::new();
+    let mut padding_size = 0;
+    let (sbt1, address) = loop {
+        let sbt1 = create_sbt().map_err(|err| out_of_memory(err, padding_size))?;
+        let address = ctx.buffer_address(&sbt1);
+        if address + SBT_BUFFER_SIZE > FOUR_GIB {
+            break (sbt1, address);
+        }
+        let heap_index = ctx.memory_properties.memory_types[sbt1.memory().memory_type_index as usize].heap_index;
+        let heap_size = ctx.memory_properties.memory_heaps[heap_index as usize].size;
+        if heap_size < FOUR_GIB + SBT_BUFFER_SIZE {
+            return Err(Skipped::missing(&format!(
+                "a {placement} memory heap larger than 4 GiB, sbt1_buffer is at {address:#x} and heap {heap_index} has \
+                 {} MiB to pad it with",
+                heap_size >> 20
+            ))
+            .into());
+        }
+        if padding_size + SBT_BUFFER_SIZE >= heap_size {
+            return Err(Skipped::missing(&format!(
+                "device addresses above 4 GiB, sbt1_buffer was still at {address:#x} after {} MiB of padding",
+                padding_size >> 20
+            ))
+            .into());
+        }
+        // The padding likely takes the place of the SBT buffer, so that the next one lands right after it, halfway
+        // across the boundary if it is close.
+        drop(sbt1);
+        let size = (FOUR_GIB - address - SBT_BUFFER_SIZE / 2).min(PADDING_SIZE);
+        let buffer = ctx
+            .create_buffer(
+                &format!("padding_{}", padding.len()),
+                size,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                placement,
+            )
+            .map_err(|err| out_of_memory(err, padding_size))?;
+        padding.push(buffer);
+        padding_size += size;
+    };
+    let base_alignment = ctx.rtx_pipeline_properties.shader_group_base_alignment as u64;
+    let locations = HighLocations::new(address, SBT_BUFFER_SIZE, base_alignment);
+    harness.adopt_sbt(sbt1);
+    Ok(HighAddress {
+        _padding: padding,
+        padding_size,
+        sbt_address: address,
+        locations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn straddles_the_boundary() {
+        let locations = HighLocations::new(FOUR_GIB - 4096, SBT_BUFFER_SIZE, 64);
+        assert_eq!(locations.straddling, Some(SbtLocation::InBuffer(4096 - 64)));
+        assert_eq!(locations.above, Some(SbtLocation::InBuffer(4096)));
+        // The next boundary counts as well.
+        let locations = HighLocations::new(2 * FOUR_GIB - 128, SBT_BUFFER_SIZE, 64);
+        assert_eq!(locations.straddling, Some(SbtLocation::InBuffer(64)));
+        assert_eq!(locations.above, Some(SbtLocation::InBuffer(0)));
+    }
+
+    #[test]
+    fn needs_room() {
+        // Already above the boundary, and starting right at the next one.
+        let locations = HighLocations::new(FOUR_GIB + 4096, SBT_BUFFER_SIZE, 64);
+        assert_eq!(locations.straddling, None);
+        assert_eq!(locations.above, Some(SbtLocation::InBuffer(0)));
+        let locations = HighLocations::new(FOUR_GIB, SBT_BUFFER_SIZE, 64);
+        assert_eq!(locations.straddling, None);
+        // The boundary is too close to the end of the buffer.
+        let locations = HighLocations::new(FOUR_GIB - SBT_BUFFER_SIZE + 4096, SBT_BUFFER_SIZE, 64);
+        assert_eq!(locations.straddling, None);
+        assert_eq!(locations.above, None);
+    }
+}
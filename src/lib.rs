@@ -0,0 +1,28 @@
+//! Building blocks of the SBT alignment repro, for use from other binaries and integration tests.
+//!
+//! [`context::RtContext`] owns the device, its queues and the ray tracing loaders. [`accel`], [`pipeline`] and
+//! [`sbt`] build acceleration structures, the test pipeline and shader binding tables on top of it, and
+//! [`harness::Harness`] puts them together to trace the test shaders with any SBT layout. [`suite`] runs registered
+//! test cases against a harness.
+//!
+//! Functions that call into Vulkan are `unsafe` for the same reason ash's are: they're sound only if the handles they
+//! are given are valid and the usual Vulkan usage rules are followed.
+
+#![allow(clippy::missing_safety_doc)]
+
+pub mod accel;
+pub mod allocator;
+pub mod context;
+pub mod error;
+pub mod fault;
+pub mod forensics;
+pub mod harness;
+pub mod high_address;
+pub mod memory;
+pub mod names;
+pub mod owned;
+pub mod pipeline;
+pub mod probe;
+pub mod sbt;
+pub mod suite;
+pub mod validation;
@@ -1,760 +1,468 @@
-use std::{ffi::c_void, println, assert_eq};
+use std::{println, time::Duration};
 
 use ash::vk;
 
+use intel_alignment_bug::{
+    allocator::{self, SubAllocator},
+    context::RtContext,
+    error::{Error, Result},
+    harness::{Harness, SbtLocation, TraceParams, HIT_RECORD_DATA, MISS_RECORD_DATA, RAYGEN_RECORD_DATA},
+    high_address,
+    memory::Placement,
+    probe::{self, Loader, Skipped},
+    sbt, suite,
+    validation::{self, Validation},
+};
+
 fn main() {
-    unsafe {
-        run();
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}");
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    // Every error ends up here, after everything created so far has been torn down.
+    match unsafe { run_args(&args) } {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(Error::Skipped(skipped)) => println!("{skipped}"),
+        // A hung GPU or lost device is the driver failing, not the harness.
+        Err(err) if err.gpu_failure() => {
+            println!("FAIL: {err}");
+            std::process::exit(1);
+        }
+        Err(err @ Error::Usage(_)) => {
+            eprintln!("error: {err}");
+            std::process::exit(2);
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(3);
+        }
+    }
+}
+
+/// Runs the command `args` asks for. Returns whether the driver passed.
+unsafe fn run_args(args: &Args) -> Result<bool> {
+    let vulkan = probe::create_instance(&args.loader, args.validate)?;
+    println!("{}", args.loader);
+    if args.validate && vulkan.validation.is_none() {
+        println!("{} isn't installed, running without validation", validation::LAYER_NAME.to_str().unwrap());
+    }
+    let devices = probe::devices(&vulkan.instance)?;
+    if let Command::ListDevices = args.command {
+        for device in &devices {
+            println!("{device}");
+        }
+        return Ok(true);
+    }
+    let device = probe::select_device(&devices, args.device.as_deref())?;
+    println!("Using device: {device}");
+    let mut ctx = RtContext::new(&vulkan, device, args.all_queue_families)?;
+    println!("{:#?}", ctx.rtx_pipeline_properties);
+    if ctx.device_fault.is_none() {
+        println!("VK_EXT_device_fault isn't available, a lost device won't be explained");
+    }
+    ctx.force_non_coherent = args.non_coherent;
+    if let Some(timeout) = args.timeout {
+        ctx.timeout = Duration::from_secs(timeout as u64);
+    }
+    if args.suballocate {
+        ctx.allocator = Some(SubAllocator::new(allocator::DEFAULT_BLOCK_SIZE));
+    }
+    let mut harness = Harness::new(ctx)?;
+    harness.poison = args.poison;
+    if args.suballocate {
+        for buffer in harness.buffers() {
+            println!("{}", harness.ctx.describe_buffer(buffer));
+        }
+    }
+    let mut passed = true;
+    for queue in 0..harness.ctx.queues.len() {
+        harness.ctx.active_queue = queue;
+        if args.all_queue_families {
+            println!("\n{}:", harness.ctx.queues[queue].family);
+        }
+        passed &= run_command(&mut harness, args)?;
+    }
+    // Tear down before looking at the validation results, so that misuse during destruction counts too.
+    let log = vulkan.validation.as_ref().map(Validation::log);
+    drop(harness);
+    drop(vulkan);
+    if let Some(log) = log {
+        // Info and verbose messages are mostly the loader and layers narrating their setup, so they're only counted.
+        for message in log.messages().iter() {
+            match message.severity {
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => println!("validation error: {}", message.text),
+                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => println!("validation warning: {}", message.text),
+                _ => {}
+            }
+        }
+        println!("{log}");
+        passed &= log.passed();
+    }
+    Ok(passed)
+}
+
+/// Runs `args.command` on the active queue of `harness`. Returns whether the driver passed.
+unsafe fn run_command(harness: &mut Harness, args: &Args) -> Result<bool> {
+    let placement = args.placement.unwrap_or_default();
+    let location = args.location.unwrap_or_default();
+    match args.command {
+        Command::Run => {
+            check_handle_aligned(harness, "--offset", args.offset)?;
+            run(harness, args.offset, placement, location)
+        }
+        Command::Sweep => {
+            check_handle_aligned(harness, "--max-offset", args.max_offset)?;
+            let placements = args.placement.map_or(Placement::ALL.to_vec(), |placement| vec![placement]);
+            let locations = args
+                .location
+                .map_or(SbtLocation::variants(DEFAULT_LOCATION_OFFSET).to_vec(), |location| vec![location]);
+            sweep(harness, args.max_offset, &placements, &locations)
+        }
+        Command::StrideSweep => stride_sweep(harness, args.max_stride, placement, location),
+        Command::HighAddress => high_address(harness, placement),
+        Command::Suite => {
+            let report = suite::run_suite(harness, &args.cases)?;
+            println!("{report}");
+            Ok(report.passed())
+        }
+        Command::ListDevices => unreachable!(),
+        Command::DetectAlignment => {
+            let report = harness.detect_base_alignment(args.max_alignment, placement, location)?;
+            println!("{report}");
+            Ok(!report.mismatch())
+        }
     }
 }
 
+const USAGE: &str = "usage: intel-alignment-bug [run] [--offset <bytes>] [<options>]
+       intel-alignment-bug sweep [--max-offset <bytes>] [<options>]
+       intel-alignment-bug stride-sweep [--max-stride <bytes>] [<options>]
+       intel-alignment-bug detect-alignment [--max-alignment <bytes>] [<options>]
+       intel-alignment-bug high-address [<options>]
+       intel-alignment-bug suite [--case <name>]... [<options>]
+       intel-alignment-bug list-devices
+
+<options> are [--poison] [--device <selector>] [--placement <placement>] [--non-coherent] [--suballocate]
+[--location <location>] [--timeout <seconds>].
+<selector> is a device index, UUID, vendor:device ID pair or part of the device name, as printed by list-devices.
+--offset and --max-offset must be multiples of shaderGroupHandleAlignment. sweep only fails on offsets that are
+multiples of the declared shaderGroupBaseAlignment, and marks the others with *.
+<placement> is the memory the SBT lives in: host-visible (the default), device-local (written through a staging
+buffer) or host-cached. sweep covers all three unless --placement is given.
+<location> is where the SBT starts: start (the default, at the start of its own buffer), buffer:<bytes> (that far
+into a larger buffer, a multiple of shaderGroupBaseAlignment) or memory:<bytes> (at the start of a buffer bound at
+least that far into its memory). sweep covers start, buffer:4096 and memory:4096 unless --location is given.
+suite runs every registered test case, or those whose name contains one of the --case values.
+high-address pads the SBT's memory heap until sbt1_buffer lies across or above 4 GiB of device address space, and
+ignores --location. It is skipped if the heap is too small.
+--timeout is how long to wait for each submission before reporting a GPU timeout, 10 seconds by default.
+--non-coherent flushes host writes and invalidates before host reads even on coherent memory.
+--suballocate places all buffers inside shared 64 MiB allocations instead of one allocation each, and prints where
+they ended up.
+Every command also takes --loader <path> to load that Vulkan loader library instead of the system one, and
+--icd <json> to make the loader use only that ICD manifest. Both are printed at the start of the output.
+Every command but list-devices also takes --all-queue-families to repeat it on each compute-capable queue family,
+and --validate to enable the Khronos validation layer and fail the run on any validation error.
+
+Exits with 1 if the driver fails, hangs or loses the device, 2 on usage errors and 3 if a Vulkan call fails.";
+
 /// On my Intel Arc A770 16GB, test passes with BASE_OFFSET = 64, but fails with BASE_OFFSET = 32 or 96.
 /// Incorrect reads are observed in the SBT.
-/// !!!! Change this into 32 to observe the bug !!!!
+/// !!!! Run with `--offset 32` to observe the bug !!!!
 const BASE_OFFSET: usize = 64;
 
-// SBT Layout:
-// |             |      Raygen          |      Raymiss     |     Hitgroup     |
-// | BASE_OFFSET |32|-SBT Data 64 bytes-|-32-|   Not used  |-32-|   Not used  |
-//                    ^^^ Incorrect read here
+/// Offset of the `buffer:` and `memory:` SBT locations the sweep covers when `--location` isn't given.
+const DEFAULT_LOCATION_OFFSET: u64 = 4096;
 
-unsafe fn create_buffer(
-    device: &ash::Device,
-    memory_type_index: u32,
-    size: u64,
-    usage: vk::BufferUsageFlags,
-) -> (vk::Buffer, vk::DeviceMemory) {
-    let buf = device
-        .create_buffer(
-            &vk::BufferCreateInfo {
-                size,
-                usage,
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
+/// Upper bound of the offset sweep when `--max-offset` isn't given.
+const DEFAULT_MAX_OFFSET: usize = 256;
 
-    let requirements = device.get_buffer_memory_requirements(buf);
-    assert!(requirements.memory_type_bits & (1 << memory_type_index) != 0);
+/// Largest stride tried by `stride-sweep` when `--max-stride` isn't given. The sweep never goes beyond
+/// `maxShaderGroupStride`.
+const DEFAULT_MAX_STRIDE: usize = 256;
 
-    let flags = vk::MemoryAllocateFlagsInfo {
-        flags: vk::MemoryAllocateFlags::DEVICE_ADDRESS,
-        ..Default::default()
-    };
-    let mem = device
-        .allocate_memory(
-            &vk::MemoryAllocateInfo {
-                allocation_size: requirements.size,
-                memory_type_index,
-                p_next: &flags as *const _ as *const _,
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
-    device.bind_buffer_memory(buf, mem, 0).unwrap();
-    (buf, mem)
-}
+/// Largest base alignment tried by `detect-alignment` when `--max-alignment` isn't given.
+const DEFAULT_MAX_ALIGNMENT: usize = 256;
 
-unsafe fn run() {
-    let entry = ash::Entry::load().unwrap();
-    let instance = entry
-        .create_instance(
-            &vk::InstanceCreateInfo {
-                p_application_info: &vk::ApplicationInfo {
-                    api_version: vk::make_api_version(0, 1, 3, 0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
-    let pdevice = instance.enumerate_physical_devices().unwrap()[0];
-    let pdevice_properties = instance.get_physical_device_properties(pdevice);
-    let device_name = std::ffi::CStr::from_ptr(pdevice_properties.device_name.as_ptr() as _);
-    println!("Using device: {}", device_name.to_str().unwrap());
+enum Command {
+    Run,
+    Sweep,
+    StrideSweep,
+    DetectAlignment,
+    HighAddress,
+    Suite,
+    ListDevices,
+}
 
+struct Args {
+    command: Command,
+    offset: usize,
+    max_offset: usize,
+    max_stride: usize,
+    max_alignment: usize,
+    poison: bool,
+    device: Option<String>,
+    all_queue_families: bool,
+    validate: bool,
+    placement: Option<Placement>,
+    non_coherent: bool,
+    suballocate: bool,
+    location: Option<SbtLocation>,
+    timeout: Option<usize>,
+    cases: Vec<String>,
+    loader: Loader,
+}
 
-    let mut rtx_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR {
-        ray_tracing_pipeline: vk::TRUE,
-        ..Default::default()
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Args, String> {
+    let mut parsed = Args {
+        command: Command::Run,
+        offset: BASE_OFFSET,
+        max_offset: DEFAULT_MAX_OFFSET,
+        max_stride: DEFAULT_MAX_STRIDE,
+        max_alignment: DEFAULT_MAX_ALIGNMENT,
+        poison: false,
+        device: None,
+        all_queue_families: false,
+        validate: false,
+        placement: None,
+        non_coherent: false,
+        suballocate: false,
+        location: None,
+        timeout: None,
+        cases: Vec::new(),
+        loader: Loader::default(),
     };
-    let mut accel_struct_features = vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
-        acceleration_structure: vk::TRUE,
-        ..Default::default()
-    };
-    let mut v12_features = vk::PhysicalDeviceVulkan12Features {
-        buffer_device_address: vk::TRUE,
-        ..Default::default()
-    };
-    let mut v13_features = vk::PhysicalDeviceVulkan13Features {
-        synchronization2: vk::TRUE,
-        ..Default::default()
-    };
-    let features = vk::PhysicalDeviceFeatures2::builder()
-        .push_next(&mut rtx_features)
-        .push_next(&mut accel_struct_features)
-        .push_next(&mut v12_features)
-        .push_next(&mut v13_features)
-        .build();
-    let device = instance
-        .create_device(
-            pdevice,
-            &vk::DeviceCreateInfo {
-                p_next: &features as *const _ as *const _,
-                queue_create_info_count: 1,
-                p_queue_create_infos: &vk::DeviceQueueCreateInfo {
-                    queue_family_index: 0,
-                    queue_count: 1,
-                    p_queue_priorities: &1.0,
-                    ..Default::default()
-                },
-                enabled_extension_count: 3,
-                pp_enabled_extension_names: [
-                    ash::extensions::khr::AccelerationStructure::name().as_ptr(),
-                    ash::extensions::khr::RayTracingPipeline::name().as_ptr(),
-                    ash::extensions::khr::DeferredHostOperations::name().as_ptr(),
-                ]
-                .as_slice()
-                .as_ptr(),
-                ..Default::default()
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "run" => parsed.command = Command::Run,
+            "sweep" => parsed.command = Command::Sweep,
+            "stride-sweep" => parsed.command = Command::StrideSweep,
+            "detect-alignment" => parsed.command = Command::DetectAlignment,
+            "high-address" => parsed.command = Command::HighAddress,
+            "suite" => parsed.command = Command::Suite,
+            "list-devices" => parsed.command = Command::ListDevices,
+            "--offset" => parsed.offset = flag_value(&mut args, "--offset")?,
+            "--max-offset" => parsed.max_offset = flag_value(&mut args, "--max-offset")?,
+            "--max-stride" => parsed.max_stride = flag_value(&mut args, "--max-stride")?,
+            "--max-alignment" => parsed.max_alignment = flag_value(&mut args, "--max-alignment")?,
+            "--poison" => parsed.poison = true,
+            "--all-queue-families" => parsed.all_queue_families = true,
+            "--validate" => parsed.validate = true,
+            "--non-coherent" => parsed.non_coherent = true,
+            "--suballocate" => parsed.suballocate = true,
+            "--timeout" => match flag_value(&mut args, "--timeout")? {
+                0 => return Err("--timeout must be at least 1 second".to_string()),
+                timeout => parsed.timeout = Some(timeout),
             },
-            None,
-        )
-        .unwrap();
-
-    let aabbs = vk::AabbPositionsKHR {
-        min_x: 0.0,
-        min_y: 0.0,
-        min_z: 0.0,
-        max_x: 1.0,
-        max_y: 1.0,
-        max_z: 1.0,
-    };
-
-    let mem_properties = instance.get_physical_device_memory_properties(pdevice);
-    let (memory_type_index, _) = mem_properties
-        .memory_types
-        .iter()
-        .take(mem_properties.memory_type_count as usize)
-        .enumerate()
-        .find(|(_, a)| {
-            a.property_flags.contains(
-                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            )
-        })
-        .unwrap();
-    let memory_type_index = memory_type_index as u32;
-
-    let (blas_input_buf, blas_input_mem) = create_buffer(
-        &device,
-        memory_type_index,
-        std::mem::size_of_val(&aabbs) as u64,
-        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
-            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-    );
-    {
-        // Write input
-        let ptr = device
-            .map_memory(
-                blas_input_mem,
-                0,
-                std::mem::size_of_val(&aabbs) as u64,
-                Default::default(),
-            )
-            .unwrap();
-        std::ptr::copy_nonoverlapping(&aabbs, ptr as *mut vk::AabbPositionsKHR, 1);
-        device.unmap_memory(blas_input_mem);
+            "--device" => parsed.device = Some(args.next().ok_or("--device expects a value")?),
+            "--placement" => parsed.placement = Some(args.next().ok_or("--placement expects a value")?.parse()?),
+            "--loader" => parsed.loader.library = Some(args.next().ok_or("--loader expects a path")?.into()),
+            "--icd" => parsed.loader.icd = Some(args.next().ok_or("--icd expects a path")?.into()),
+            "--case" => parsed.cases.push(args.next().ok_or("--case expects a value")?),
+            "--location" => parsed.location = Some(args.next().ok_or("--location expects a value")?.parse()?),
+            _ => return Err(format!("unexpected argument {arg:?}")),
+        }
     }
+    Ok(parsed)
+}
 
-    let accel_struct_loader = ash::extensions::khr::AccelerationStructure::new(&instance, &device);
-
-    // Create BLAS
-    let blas_build_sizes = accel_struct_loader.get_acceleration_structure_build_sizes(
-        vk::AccelerationStructureBuildTypeKHR::DEVICE,
-        &vk::AccelerationStructureBuildGeometryInfoKHR {
-            ty: vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
-            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
-            geometry_count: 1,
-            p_geometries: [vk::AccelerationStructureGeometryKHR {
-                geometry_type: vk::GeometryTypeKHR::AABBS,
-                flags: vk::GeometryFlagsKHR::OPAQUE,
-                geometry: vk::AccelerationStructureGeometryDataKHR {
-                    aabbs: vk::AccelerationStructureGeometryAabbsDataKHR {
-                        ..Default::default()
-                    },
-                },
-                ..Default::default()
-            }]
-            .as_slice()
-            .as_ptr(),
-            ..Default::default()
-        },
-        &[1],
-    );
-    let (blas_backing_buf, _) = create_buffer(
-        &device,
-        memory_type_index,
-        blas_build_sizes.acceleration_structure_size,
-        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR,
-    );
-
-    let blas = accel_struct_loader
-        .create_acceleration_structure(
-            &vk::AccelerationStructureCreateInfoKHR {
-                buffer: blas_backing_buf,
-                offset: 0,
-                size: blas_build_sizes.acceleration_structure_size,
-                ty: vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
-
-    // Create TLAS
-    let tlas_build_sizes = accel_struct_loader.get_acceleration_structure_build_sizes(
-        vk::AccelerationStructureBuildTypeKHR::DEVICE,
-        &vk::AccelerationStructureBuildGeometryInfoKHR {
-            ty: vk::AccelerationStructureTypeKHR::TOP_LEVEL,
-            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
-            geometry_count: 1,
-            p_geometries: [vk::AccelerationStructureGeometryKHR {
-                geometry_type: vk::GeometryTypeKHR::INSTANCES,
-                flags: vk::GeometryFlagsKHR::OPAQUE,
-                geometry: vk::AccelerationStructureGeometryDataKHR {
-                    instances: vk::AccelerationStructureGeometryInstancesDataKHR {
-                        ..Default::default()
-                    },
-                },
-                ..Default::default()
-            }]
-            .as_slice()
-            .as_ptr(),
-            ..Default::default()
-        },
-        &[1],
-    );
-    let (tlas_backing_buf, _) = create_buffer(
-        &device,
-        memory_type_index,
-        tlas_build_sizes.acceleration_structure_size,
-        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR,
-    );
-
-    let tlas = accel_struct_loader
-        .create_acceleration_structure(
-            &vk::AccelerationStructureCreateInfoKHR {
-                buffer: tlas_backing_buf,
-                offset: 0,
-                size: tlas_build_sizes.acceleration_structure_size,
-                ty: vk::AccelerationStructureTypeKHR::TOP_LEVEL,
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
+fn flag_value(args: &mut impl Iterator<Item = String>, flag: &str) -> std::result::Result<usize, String> {
+    let value = args.next().ok_or_else(|| format!("{flag} expects a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a whole number, got {value:?}"))
+}
 
-    let instances: vk::AccelerationStructureInstanceKHR = vk::AccelerationStructureInstanceKHR {
-        transform: vk::TransformMatrixKHR {
-            matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
-        },
-        instance_custom_index_and_mask: vk::Packed24_8::new(0, u8::MAX),
-        instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(0, 0),
-        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
-            device_handle: accel_struct_loader.get_acceleration_structure_device_address(
-                &vk::AccelerationStructureDeviceAddressInfoKHR {
-                    acceleration_structure: blas,
-                    ..Default::default()
-                },
-            ),
-        },
-    };
-    let (tlas_input_buf, tlas_input_mem) = create_buffer(
-        &device,
-        memory_type_index,
-        tlas_build_sizes.acceleration_structure_size,
-        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
-            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-    );
-    {
-        // Write input
-        let ptr = device
-            .map_memory(
-                tlas_input_mem,
-                0,
-                std::mem::size_of_val(&instances) as u64,
-                Default::default(),
-            )
-            .unwrap();
-        std::ptr::copy_nonoverlapping(&instances, ptr as *mut vk::AccelerationStructureInstanceKHR, 1);
-        device.unmap_memory(tlas_input_mem);
+/// Checks that `value`, given with `flag`, is a multiple of `shaderGroupHandleAlignment`, since shader records must
+/// start at one.
+fn check_handle_aligned(harness: &Harness, flag: &str, value: usize) -> Result<()> {
+    let step = harness.ctx.rtx_pipeline_properties.shader_group_handle_alignment as usize;
+    if !value.is_multiple_of(step) {
+        return Err(Error::Usage(format!(
+            "{flag} must be a multiple of shaderGroupHandleAlignment ({step}), got {value}"
+        )));
     }
+    Ok(())
+}
 
-    let (scratch_buf, _) = create_buffer(
-        &device,
-        memory_type_index,
-        tlas_build_sizes
-            .build_scratch_size
-            .max(blas_build_sizes.build_scratch_size),
-        vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-    );
-
-    let command_pool = device
-        .create_command_pool(&Default::default(), None)
-        .unwrap();
-    let command_buffer = device
-        .allocate_command_buffers(&vk::CommandBufferAllocateInfo {
-            command_pool,
-            level: vk::CommandBufferLevel::PRIMARY,
-            command_buffer_count: 1,
-            ..Default::default()
-        })
-        .unwrap()[0];
-
-    device
-        .begin_command_buffer(command_buffer, &Default::default())
-        .unwrap();
-    accel_struct_loader.cmd_build_acceleration_structures(
-        command_buffer,
-        &[vk::AccelerationStructureBuildGeometryInfoKHR {
-            ty: vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
-            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
-            mode: vk::BuildAccelerationStructureModeKHR::BUILD,
-            dst_acceleration_structure: blas,
-            geometry_count: 1,
-            p_geometries: &vk::AccelerationStructureGeometryKHR {
-                geometry_type: vk::GeometryTypeKHR::AABBS,
-                geometry: vk::AccelerationStructureGeometryDataKHR {
-                    aabbs: vk::AccelerationStructureGeometryAabbsDataKHR {
-                        data: vk::DeviceOrHostAddressConstKHR {
-                            device_address: device.get_buffer_device_address(
-                                &vk::BufferDeviceAddressInfo {
-                                    buffer: blas_input_buf,
-                                    ..Default::default()
-                                },
-                            ),
-                        },
-                        stride: std::mem::size_of_val(&aabbs) as u64,
-                        ..Default::default()
-                    },
-                },
-                flags: vk::GeometryFlagsKHR::OPAQUE,
-                ..Default::default()
-            },
-            scratch_data: vk::DeviceOrHostAddressKHR {
-                device_address: device.get_buffer_device_address(&vk::BufferDeviceAddressInfo {
-                    buffer: scratch_buf,
-                    ..Default::default()
-                }),
-            },
-            ..Default::default()
-        }],
-        &[&[vk::AccelerationStructureBuildRangeInfoKHR {
-            primitive_count: 1,
-            primitive_offset: 0,
-            first_vertex: 0,
-            transform_offset: 0,
-        }]],
-    );
-    device.cmd_pipeline_barrier2(
-        command_buffer,
-        &vk::DependencyInfo {
-            memory_barrier_count: 1,
-            p_memory_barriers: &vk::MemoryBarrier2KHR {
-                src_stage_mask: vk::PipelineStageFlags2KHR::ACCELERATION_STRUCTURE_BUILD_KHR,
-                dst_stage_mask: vk::PipelineStageFlags2KHR::ACCELERATION_STRUCTURE_BUILD_KHR,
-                src_access_mask: vk::AccessFlags2KHR::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
-                dst_access_mask: vk::AccessFlags2KHR::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
-                ..Default::default()
-            },
-            ..Default::default()
-        },
+/// Traces the SBT at `base_offset` and checks that every shader read its record. Returns false if any didn't.
+unsafe fn run(harness: &mut Harness, base_offset: usize, placement: Placement, location: SbtLocation) -> Result<bool> {
+    let result = harness.trace(TraceParams {
+        placement,
+        location,
+        ..TraceParams::at_offset(base_offset)
+    })?;
+    println!("{}", harness.ctx.describe_buffer(harness.sbt1.as_ref().unwrap()));
+    println!(
+        "SBT at device address {:#x} ({location}), raygen region at {:#x}",
+        result.sbt_address,
+        result.sbt_address + base_offset as u64
     );
-    accel_struct_loader.cmd_build_acceleration_structures(
-        command_buffer,
-        &[vk::AccelerationStructureBuildGeometryInfoKHR {
-            ty: vk::AccelerationStructureTypeKHR::TOP_LEVEL,
-            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
-            mode: vk::BuildAccelerationStructureModeKHR::BUILD,
-            dst_acceleration_structure: tlas,
-            geometry_count: 1,
-            p_geometries: &vk::AccelerationStructureGeometryKHR {
-                geometry_type: vk::GeometryTypeKHR::INSTANCES,
-                geometry: vk::AccelerationStructureGeometryDataKHR {
-                    instances: vk::AccelerationStructureGeometryInstancesDataKHR {
-                        array_of_pointers: vk::FALSE,
-                        data: vk::DeviceOrHostAddressConstKHR {
-                            device_address: device.get_buffer_device_address(
-                                &vk::BufferDeviceAddressInfo {
-                                    buffer: tlas_input_buf,
-                                    ..Default::default()
-                                },
-                            ),
-                        },
-                        ..Default::default()
-                    },
-                },
-                flags: vk::GeometryFlagsKHR::OPAQUE,
-                ..Default::default()
-            },
-            scratch_data: vk::DeviceOrHostAddressKHR {
-                device_address: device.get_buffer_device_address(&vk::BufferDeviceAddressInfo {
-                    buffer: scratch_buf,
-                    ..Default::default()
-                }),
-            },
-            ..Default::default()
-        }],
-        &[&[vk::AccelerationStructureBuildRangeInfoKHR {
-            primitive_count: 1,
-            primitive_offset: 0,
-            first_vertex: 0,
-            transform_offset: 0,
-        }]],
-    );
-    device.end_command_buffer(command_buffer).unwrap();
-
-    let queue = device.get_device_queue(0, 0);
-    device
-        .queue_submit(
-            queue,
-            &[vk::SubmitInfo {
-                command_buffer_count: 1,
-                p_command_buffers: &command_buffer,
-                ..Default::default()
-            }],
-            vk::Fence::null(),
-        )
-        .unwrap();
-    device.queue_wait_idle(queue).unwrap();
-    device
-        .reset_command_pool(command_pool, Default::default())
-        .unwrap();
-
-    let desc_set_layout = device
-        .create_descriptor_set_layout(
-            &vk::DescriptorSetLayoutCreateInfo {
-                flags: vk::DescriptorSetLayoutCreateFlags::empty(),
-                binding_count: 2,
-                p_bindings: [
-                    vk::DescriptorSetLayoutBinding {
-                        binding: 0,
-                        descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
-                        descriptor_count: 1,
-                        stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
-                        ..Default::default()
-                    },
-                    vk::DescriptorSetLayoutBinding {
-                        binding: 1,
-                        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-                        descriptor_count: 1,
-                        stage_flags: vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::INTERSECTION_KHR
-                            | vk::ShaderStageFlags::MISS_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                        ..Default::default()
-                    },
-                ]
-                .as_slice()
-                .as_ptr(),
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
-    let pipeline_layout = device
-        .create_pipeline_layout(
-            &vk::PipelineLayoutCreateInfo {
-                set_layout_count: 1,
-                p_set_layouts: &desc_set_layout,
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
-    // Now, create pipeline.
-
-    let raygen_code = include_bytes!("test.rgen.spv");
-    let miss_code = include_bytes!("test.rmiss.spv");
-    let rint_code = include_bytes!("test.rint.spv");
-    let rchit_code = include_bytes!("test.rchit.spv");
-    let rtx_pipeline_loader = ash::extensions::khr::RayTracingPipeline::new(&instance, &device);
-    let pipeline = rtx_pipeline_loader
-        .create_ray_tracing_pipelines(
-            vk::DeferredOperationKHR::null(),
-            vk::PipelineCache::null(),
-            &[vk::RayTracingPipelineCreateInfoKHR {
-                stage_count: 4,
-                p_stages: [
-                    vk::PipelineShaderStageCreateInfo {
-                        stage: vk::ShaderStageFlags::RAYGEN_KHR,
-                        module: device
-                            .create_shader_module(
-                                &vk::ShaderModuleCreateInfo {
-                                    flags: vk::ShaderModuleCreateFlags::empty(),
-                                    code_size: raygen_code.len(),
-                                    p_code: raygen_code.as_ptr() as *const _,
-                                    ..Default::default()
-                                },
-                                None,
-                            )
-                            .unwrap(),
-                        p_name: b"main\0".as_ptr() as *const i8,
-                        ..Default::default()
-                    },
-                    vk::PipelineShaderStageCreateInfo {
-                        stage: vk::ShaderStageFlags::MISS_KHR,
-                        module: device
-                            .create_shader_module(
-                                &vk::ShaderModuleCreateInfo {
-                                    flags: vk::ShaderModuleCreateFlags::empty(),
-                                    code_size: miss_code.len(),
-                                    p_code: miss_code.as_ptr() as *const _,
-                                    ..Default::default()
-                                },
-                                None,
-                            )
-                            .unwrap(),
-                        p_name: b"main\0".as_ptr() as *const i8,
-                        ..Default::default()
-                    },
-                    vk::PipelineShaderStageCreateInfo {
-                        stage: vk::ShaderStageFlags::INTERSECTION_KHR,
-                        module: device
-                            .create_shader_module(
-                                &vk::ShaderModuleCreateInfo {
-                                    flags: vk::ShaderModuleCreateFlags::empty(),
-                                    code_size: rint_code.len(),
-                                    p_code: rint_code.as_ptr() as *const _,
-                                    ..Default::default()
-                                },
-                                None,
-                            )
-                            .unwrap(),
-                        p_name: b"main\0".as_ptr() as *const i8,
-                        ..Default::default()
-                    },
-                    vk::PipelineShaderStageCreateInfo {
-                        stage: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
-                        module: device
-                            .create_shader_module(
-                                &vk::ShaderModuleCreateInfo {
-                                    flags: vk::ShaderModuleCreateFlags::empty(),
-                                    code_size: rchit_code.len(),
-                                    p_code: rchit_code.as_ptr() as *const _,
-                                    ..Default::default()
-                                },
-                                None,
-                            )
-                            .unwrap(),
-                        p_name: b"main\0".as_ptr() as *const i8,
-                        ..Default::default()
-                    },
-                ]
-                .as_slice()
-                .as_ptr(),
-                group_count: 3,
-                p_groups: [
-                    vk::RayTracingShaderGroupCreateInfoKHR {
-                        ty: vk::RayTracingShaderGroupTypeKHR::GENERAL,
-                        general_shader: 0, // rgen
-                        any_hit_shader: vk::SHADER_UNUSED_KHR,
-                        closest_hit_shader: vk::SHADER_UNUSED_KHR,
-                        intersection_shader: vk::SHADER_UNUSED_KHR,
-                        ..Default::default()
-                    },
-                    vk::RayTracingShaderGroupCreateInfoKHR {
-                        ty: vk::RayTracingShaderGroupTypeKHR::GENERAL,
-                        general_shader: 1, // rmiss
-                        any_hit_shader: vk::SHADER_UNUSED_KHR,
-                        closest_hit_shader: vk::SHADER_UNUSED_KHR,
-                        intersection_shader: vk::SHADER_UNUSED_KHR,
-                        ..Default::default()
-                    },
-                    vk::RayTracingShaderGroupCreateInfoKHR {
-                        ty: vk::RayTracingShaderGroupTypeKHR::PROCEDURAL_HIT_GROUP,
-                        intersection_shader: 2,
-                        any_hit_shader: vk::SHADER_UNUSED_KHR,
-                        closest_hit_shader: 3,
-                        general_shader: vk::SHADER_UNUSED_KHR,
-                        ..Default::default()
-                    },
-                ]
-                .as_slice()
-                .as_ptr(),
-                max_pipeline_ray_recursion_depth: 1,
-                layout: pipeline_layout,
-                ..Default::default()
-            }],
-            None,
-        )
-        .unwrap()[0];
-
-    let mut properties = vk::PhysicalDeviceProperties2::default();
-    let mut rtx_pipeline_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR =
-        vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
-    properties.p_next = &mut rtx_pipeline_properties as *mut _ as *mut _;
-    instance.get_physical_device_properties2(pdevice, &mut properties);
-    println!("{:#?}", rtx_pipeline_properties);
-
-    let group_handles = rtx_pipeline_loader
-        .get_ray_tracing_shader_group_handles(
-            pipeline,
-            0,
-            3,
-            rtx_pipeline_properties.shader_group_handle_size as usize * 3, // On both NV and Intel, this is 32 * 3
-        )
-        .unwrap();
+    result.for_each_corruption(|report| println!("{report}\n"));
+    // Every stage copies its shader record into the results buffer, check that they're the same as the ones we put in.
+    let failed = result.failed_stages();
+    if failed.is_empty() {
+        println!("Test passed!");
+    } else {
+        println!("Test failed, shaders that didn't read their record: {}", failed.join(", "));
+    }
+    Ok(result.passed())
+}
 
-    let (results_buffer, results_memory) = create_buffer(
-        &device,
-        memory_type_index,
-        1000,
-        vk::BufferUsageFlags::STORAGE_BUFFER,
-    );
-    {
-        let ptr = device
-            .map_memory(results_memory, 0, 1000, Default::default())
-            .unwrap() as *mut u8;
-        for i in 0..1000 {
-            *ptr.add(i) = 0;
+/// Traces the same SBT layout at every multiple of `shaderGroupHandleAlignment` up to `max_offset`, with the SBT in
+/// each of `placements` at each of `locations`, and prints a pass/fail matrix per combination. Returns false if any
+/// offset that is a multiple of the declared `shaderGroupBaseAlignment` failed. The offsets in between break
+/// VUID-vkCmdTraceRaysKHR-pRayGenShaderBindingTable-03682, so they are traced and marked for information only.
+unsafe fn sweep(
+    harness: &mut Harness,
+    max_offset: usize,
+    placements: &[Placement],
+    locations: &[SbtLocation],
+) -> Result<bool> {
+    let step = harness.ctx.rtx_pipeline_properties.shader_group_handle_alignment as usize;
+    let base_alignment = harness.ctx.rtx_pipeline_properties.shader_group_base_alignment as usize;
+    println!("Offset sweep: shaderGroupHandleAlignment = {step}, shaderGroupBaseAlignment = {base_alignment}");
+    if base_alignment > step {
+        println!("Offsets marked * are below the declared shaderGroupBaseAlignment and don't count toward failure.");
+    }
+    let verdict = |ok: bool| if ok { "pass" } else { "FAIL" };
+    let mut all_passed = true;
+    for &placement in placements {
+        for &location in locations {
+            harness.reserve_sbt(0, placement, location)?;
+            let sbt1 = harness.sbt1.as_ref().unwrap();
+            // The memory type may be a fallback if the device lacks the preferred one.
+            println!("\nSBT in {placement} memory at {location}, {}:", harness.ctx.describe_buffer(sbt1));
+            println!(
+                "{:>8}  {:<20}{:<8}{:<14}{:<13}{:<6}",
+                "offset", "raygen address", "raygen", "intersection", "closest hit", "miss"
+            );
+            for offset in (0..=max_offset).step_by(step) {
+                let result = harness.trace(TraceParams {
+                    placement,
+                    location,
+                    ..TraceParams::at_offset(offset)
+                })?;
+                let declared = offset.is_multiple_of(base_alignment);
+                println!(
+                    "{:>8}  {:<20}{:<8}{:<14}{:<13}{:<6}",
+                    format!("{}{offset}", if declared { "" } else { "*" }),
+                    format!("{:#x}", result.sbt_address + offset as u64),
+                    verdict(result.raygen_ok()),
+                    verdict(result.intersection_ok()),
+                    verdict(result.closest_hit_ok()),
+                    verdict(result.miss_ok())
+                );
+                result.for_each_corruption(|report| println!("          {}: {}", report.name, report.verdict()));
+                all_passed &= result.passed() || !declared;
+            }
         }
-        device.unmap_memory(results_memory);
     }
+    Ok(all_passed)
+}
 
-    let (sbt1_buffer, sbt1_memory) = create_buffer(
-        &device,
-        memory_type_index,
-        1000,
-        vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR
-            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+/// Traces every combination of raygen, miss and hit region strides, from the smallest stride that fits each region's
+/// records up to `max_stride` (capped at `maxShaderGroupStride`) in steps of `shaderGroupHandleAlignment`. The rays use
+/// the second miss and hit group record, so the strides actually move the records they read. Prints the failing
+/// combinations and returns false if there were any.
+unsafe fn stride_sweep(
+    harness: &mut Harness,
+    max_stride: usize,
+    placement: Placement,
+    location: SbtLocation,
+) -> Result<bool> {
+    let properties = harness.ctx.rtx_pipeline_properties;
+    let step = properties.shader_group_handle_alignment as u64;
+    let max_stride = (max_stride as u64).min(properties.max_shader_group_stride as u64);
+    let handle_size = properties.shader_group_handle_size as u64;
+    let smallest_stride = |data: &[u32]| sbt::align_up(handle_size + std::mem::size_of_val(data) as u64, step);
+    let strides = |data: &[u32]| (smallest_stride(data)..=max_stride).step_by(step as usize);
+    println!(
+        "Stride sweep: shaderGroupHandleAlignment = {}, maxShaderGroupStride = {}, up to {}",
+        step, properties.max_shader_group_stride, max_stride
     );
-    {
-        let ptr = device
-            .map_memory(sbt1_memory, 0, 1000, Default::default())
-            .unwrap() as *mut u8;
-
-        let sbt_data: [u32; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
-        std::ptr::copy_nonoverlapping(group_handles.as_ptr(), ptr.add(0 + BASE_OFFSET), 32);
-        std::ptr::copy_nonoverlapping(sbt_data.as_ptr() as *const u8, ptr.add(0 + BASE_OFFSET + 32), 64);
-
-        std::ptr::copy_nonoverlapping(group_handles.as_ptr().add(32), ptr.add(96 + BASE_OFFSET), 32);
-
-        std::ptr::copy_nonoverlapping(group_handles.as_ptr().add(64), ptr.add(96*2 + BASE_OFFSET), 32);
-        device.unmap_memory(sbt1_memory);
+    let regions = [("raygen", &RAYGEN_RECORD_DATA[..]), ("miss", &MISS_RECORD_DATA), ("hit", &HIT_RECORD_DATA)];
+    for (region, data) in regions {
+        if smallest_stride(data) > max_stride {
+            return Err(Error::Usage(format!(
+                "no {region} stride up to {max_stride} fits its records, the smallest is {}",
+                smallest_stride(data)
+            )));
+        }
     }
 
-    let descriptor_pool = device
-        .create_descriptor_pool(
-            &vk::DescriptorPoolCreateInfo {
-                max_sets: 1,
-                pool_size_count: 2,
-                p_pool_sizes: [
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::STORAGE_BUFFER,
-                        descriptor_count: 1,
-                    },
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
-                        descriptor_count: 1,
-                    },
-                ]
-                .as_slice()
-                .as_ptr(),
-                ..Default::default()
-            },
-            None,
-        )
-        .unwrap();
-    let desc_set = device
-        .allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo {
-            descriptor_pool,
-            descriptor_set_count: 1,
-            p_set_layouts: &desc_set_layout,
-            ..Default::default()
-        })
-        .unwrap()[0];
+    let (mut total, mut failed) = (0, 0);
+    for raygen_stride in strides(&RAYGEN_RECORD_DATA) {
+        for miss_stride in strides(&MISS_RECORD_DATA) {
+            for hit_stride in strides(&HIT_RECORD_DATA) {
+                let result = harness.trace(TraceParams {
+                    base_offset: 0,
+                    strides: [Some(raygen_stride), Some(miss_stride), Some(hit_stride)],
+                    record_index: 1,
+                    placement,
+                    location,
+                })?;
+                total += 1;
+                if result.passed() {
+                    continue;
+                }
+                failed += 1;
+                println!(
+                    "FAIL: raygen stride {raygen_stride}, miss stride {miss_stride}, hit stride {hit_stride}: {}",
+                    result.failed_stages().join(", ")
+                );
+                result.for_each_corruption(|report| println!("      {}: {}", report.name, report.verdict()));
+            }
+        }
+    }
+    println!("{failed} of {total} stride combinations failed");
+    Ok(failed == 0)
+}
 
-    device.update_descriptor_sets(
-        &[
-            vk::WriteDescriptorSet {
-                dst_set: desc_set,
-                dst_binding: 1,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-                p_buffer_info: &vk::DescriptorBufferInfo {
-                    buffer: results_buffer,
-                    offset: 0,
-                    range: 1000,
-                },
-                ..Default::default()
-            },
-            vk::WriteDescriptorSet {
-                dst_set: desc_set,
-                dst_binding: 0,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
-                p_next: &vk::WriteDescriptorSetAccelerationStructureKHR {
-                    acceleration_structure_count: 1,
-                    p_acceleration_structures: &tlas,
-                    ..Default::default()
-                } as *const _ as *const c_void,
-                ..Default::default()
-            },
-        ],
-        &[],
+/// Traces with `sbt1_buffer` pushed to a high device address by [`high_address::place_sbt_high`], once with the raygen
+/// record straddling a multiple of 4 GiB and once with the whole SBT above 4 GiB, as far as the buffer allows. Prints
+/// the device addresses of the regions along with the results and returns false if either trace failed.
+unsafe fn high_address(harness: &mut Harness, placement: Placement) -> Result<bool> {
+    let high = high_address::place_sbt_high(harness, placement)?;
+    println!(
+        "High-address mode: {} MiB of padding, {}",
+        high.padding_size >> 20,
+        harness.ctx.describe_buffer(harness.sbt1.as_ref().unwrap())
     );
-
-
-
-
-    device.begin_command_buffer(command_buffer, &Default::default()).unwrap();
-    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::RAY_TRACING_KHR, pipeline);
-    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::RAY_TRACING_KHR, pipeline_layout, 0, &[desc_set], &[]);
-    let base_address = device.get_buffer_device_address(
-        &vk::BufferDeviceAddressInfo {
-            buffer: sbt1_buffer,
+    let cases = [("straddling 4 GiB", high.locations.straddling), ("above 4 GiB", high.locations.above)];
+    if cases.iter().all(|(_, location)| location.is_none()) {
+        return Err(Skipped::missing(&format!(
+            "room for the SBT across or above 4 GiB, sbt1_buffer is at {:#x}",
+            high.sbt_address
+        ))
+        .into());
+    }
+    let mut all_passed = true;
+    for (name, location) in cases {
+        let Some(location) = location else {
+            println!("{name}: no room in sbt1_buffer");
+            continue;
+        };
+        let result = harness.trace(TraceParams {
+            placement,
+            location,
             ..Default::default()
-        }
-    );
-    assert!(base_address % 64 == 0);
-    rtx_pipeline_loader.cmd_trace_rays(command_buffer,
-        &vk::StridedDeviceAddressRegionKHR {
-            device_address: base_address + 0 + BASE_OFFSET as u64,
-            stride: 96,
-            size: 96,
-        },
-        &vk::StridedDeviceAddressRegionKHR {
-            device_address: base_address + 96 + BASE_OFFSET as u64,
-            stride: 96,
-            size: 96,
-        },
-        &vk::StridedDeviceAddressRegionKHR {
-            device_address: base_address + 96*2 + BASE_OFFSET as u64,
-            stride: 96,
-            size: 96,
-        }, &vk::StridedDeviceAddressRegionKHR::default(), 1, 1, 1);
-    device.end_command_buffer(command_buffer).unwrap();
-
-    device.queue_submit(queue, &[vk::SubmitInfo {
-        command_buffer_count: 1,
-        p_command_buffers: &command_buffer,
-        ..Default::default()
-    }], vk::Fence::null()).unwrap();
-    device.queue_wait_idle(queue).unwrap();
-
-
-
-    let ptr = device.map_memory(results_memory, 0, 1000, Default::default()).unwrap() as *mut u8;
-    let results = std::slice::from_raw_parts(ptr as *const u8 as *const u32, 1000);
-
-    for i in 0..16 {
-        // The ray gen shader copies all raygen data into the results buffer. Assert that they're the same as the one
-        // we put in, on line 633.
-        assert_eq!(results[i], i as u32);
+        })?;
+        let [raygen, miss, hit, _] = result.layout.device_regions(result.sbt_address);
+        println!(
+            "{name} ({location}): raygen region at {:#x}, miss region at {:#x}, hit region at {:#x}: {}",
+            raygen.device_address,
+            miss.device_address,
+            hit.device_address,
+            if result.passed() { "pass" } else { "FAIL" }
+        );
+        result.for_each_corruption(|report| println!("    {}: {}", report.name, report.verdict()));
+        all_passed &= result.passed();
     }
-    assert_eq!(results[17], 12777); // intersection shader ran
-    assert_eq!(results[16], 120000); // closest hit shader ran
-    println!("Test passed!");
+    Ok(all_passed)
 }
@@ -0,0 +1,178 @@
+//! Memory placement policies. Each buffer asks for a [`Placement`], which picks the first memory type from a list of
+//! preferences, so that every policy still works on devices that lack the ideal memory type, e.g. without resizable
+//! BAR.
+
+use std::{fmt, str::FromStr};
+
+use ash::vk;
+
+use crate::sbt::align_up;
+
+/// Where a buffer's memory lives and how the host gets data into it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Placement {
+    /// Mapped and written directly. Prefers device-local memory the host can see (resizable BAR), falls back to
+    /// plain host memory.
+    #[default]
+    HostVisible,
+    /// Device-local memory, preferably not host-visible, written and read back through a staging buffer.
+    DeviceLocal,
+    /// Mapped host memory that the CPU caches, which falls back to uncached host memory.
+    HostCached,
+}
+
+impl Placement {
+    pub const ALL: [Placement; 3] = [Placement::HostVisible, Placement::DeviceLocal, Placement::HostCached];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Placement::HostVisible => "host-visible",
+            Placement::DeviceLocal => "device-local",
+            Placement::HostCached => "host-cached",
+        }
+    }
+
+    /// Memory property flags a memory type must have and must not have, most preferred first. Host-visible
+    /// placements prefer coherent memory but take non-coherent memory over nothing.
+    fn preferences(self) -> Vec<(vk::MemoryPropertyFlags, vk::MemoryPropertyFlags)> {
+        let none = vk::MemoryPropertyFlags::empty();
+        let host = vk::MemoryPropertyFlags::HOST_VISIBLE;
+        let coherent = vk::MemoryPropertyFlags::HOST_COHERENT;
+        let device_local = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let cached = vk::MemoryPropertyFlags::HOST_CACHED;
+        match self {
+            Placement::HostVisible => vec![
+                (host | coherent | device_local, none),
+                (host | device_local, none),
+                (host | coherent, none),
+                (host, none),
+            ],
+            Placement::DeviceLocal => vec![(device_local, host), (device_local, none)],
+            Placement::HostCached => vec![
+                (host | coherent | cached, none),
+                (host | cached, none),
+                (host | coherent, none),
+                (host, none),
+            ],
+        }
+    }
+
+    /// Buffer usage the placement needs on top of what the buffer is for.
+    pub fn extra_usage(self) -> vk::BufferUsageFlags {
+        match self {
+            Placement::DeviceLocal => vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            Placement::HostVisible | Placement::HostCached => vk::BufferUsageFlags::empty(),
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        Placement::ALL
+            .into_iter()
+            .find(|placement| placement.name() == name)
+            .ok_or_else(|| format!("unknown placement {name:?}, expected host-visible, device-local or host-cached"))
+    }
+}
+
+/// The memory type a buffer with `placement` is allocated from, given the `memoryTypeBits` of the buffer. Returns
+/// the index of the memory type and its property flags.
+pub fn find_memory_type(
+    properties: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+    placement: Placement,
+) -> Option<(u32, vk::MemoryPropertyFlags)> {
+    let types = &properties.memory_types[..properties.memory_type_count as usize];
+    placement.preferences().into_iter().find_map(|(required, avoided)| {
+        (0..)
+            .zip(types)
+            .filter(|(index, _)| type_bits & (1 << index) != 0)
+            .map(|(index, memory_type)| (index, memory_type.property_flags))
+            .find(|(_, flags)| flags.contains(required) && !flags.intersects(avoided))
+    })
+}
+
+/// The `(offset, size)` of the `VkMappedMemoryRange` to flush or invalidate so that it covers `size` bytes at
+/// `offset` of an allocation of `allocation_size` bytes. The range is widened to multiples of `nonCoherentAtomSize`,
+/// and becomes `VK_WHOLE_SIZE` when that would reach the end of the allocation.
+pub fn atom_range(offset: u64, size: u64, atom_size: u64, allocation_size: u64) -> (u64, u64) {
+    let start = offset / atom_size * atom_size;
+    let end = align_up(offset + size, atom_size);
+    if end >= allocation_size {
+        (start, vk::WHOLE_SIZE)
+    } else {
+        (start, end - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE_LOCAL: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+    const CACHED: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::HOST_CACHED;
+    const HOST: vk::MemoryPropertyFlags = vk::MemoryPropertyFlags::from_raw(
+        vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw() | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
+    );
+
+    fn properties(types: &[vk::MemoryPropertyFlags]) -> vk::PhysicalDeviceMemoryProperties {
+        let mut properties = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: types.len() as u32,
+            ..Default::default()
+        };
+        for (memory_type, &flags) in properties.memory_types.iter_mut().zip(types) {
+            memory_type.property_flags = flags;
+        }
+        properties
+    }
+
+    fn find(types: &[vk::MemoryPropertyFlags], type_bits: u32, placement: Placement) -> Option<u32> {
+        find_memory_type(&properties(types), type_bits, placement).map(|(index, _)| index)
+    }
+
+    #[test]
+    fn prefers_ideal_memory_types() {
+        // A discrete GPU with resizable BAR.
+        let types = [DEVICE_LOCAL, HOST, HOST | CACHED, DEVICE_LOCAL | HOST];
+        assert_eq!(find(&types, !0, Placement::HostVisible), Some(3));
+        assert_eq!(find(&types, !0, Placement::DeviceLocal), Some(0));
+        assert_eq!(find(&types, !0, Placement::HostCached), Some(2));
+    }
+
+    #[test]
+    fn falls_back() {
+        // A discrete GPU without resizable BAR has no host-visible device-local memory.
+        let types = [DEVICE_LOCAL, HOST];
+        assert_eq!(find(&types, !0, Placement::HostVisible), Some(1));
+        assert_eq!(find(&types, !0, Placement::HostCached), Some(1));
+        // An integrated GPU, where all memory is device-local and host-visible.
+        let types = [DEVICE_LOCAL | HOST, DEVICE_LOCAL | HOST | CACHED];
+        assert_eq!(find(&types, !0, Placement::DeviceLocal), Some(0));
+        // Memory types the buffer doesn't allow are skipped.
+        assert_eq!(find(&types, 0b10, Placement::HostVisible), Some(1));
+        assert_eq!(find(&[DEVICE_LOCAL], !0, Placement::HostVisible), None);
+        // Non-coherent memory is better than none.
+        let non_coherent = vk::MemoryPropertyFlags::HOST_VISIBLE;
+        assert_eq!(find(&[DEVICE_LOCAL, non_coherent], !0, Placement::HostVisible), Some(1));
+        assert_eq!(find(&[non_coherent, HOST | CACHED], !0, Placement::HostCached), Some(1));
+        assert_eq!(find(&[HOST, non_coherent | CACHED], !0, Placement::HostCached), Some(1));
+    }
+
+    #[test]
+    fn widens_ranges_to_atoms() {
+        assert_eq!(atom_range(0, 1000, 64, 4096), (0, 1024));
+        assert_eq!(atom_range(100, 28, 64, 4096), (64, 64));
+        assert_eq!(atom_range(0, 1024, 64, 4096), (0, 1024));
+        // Ranges that reach the end of the allocation, which needn't be a multiple of the atom size.
+        assert_eq!(atom_range(0, 1000, 64, 1000), (0, vk::WHOLE_SIZE));
+        assert_eq!(atom_range(960, 30, 256, 1000), (768, vk::WHOLE_SIZE));
+    }
+}
@@ -0,0 +1,62 @@
+//! Debug names and command buffer labels through `VK_EXT_debug_utils`, so that validation messages, crash dumps
+//! and captures say `sbt1_buffer` or "trace rays" instead of a bare handle.
+
+use std::ffi::CString;
+
+use ash::{extensions::ext::DebugUtils, vk};
+
+use crate::error::{Context, Result};
+
+/// Names objects of one device. Every call is a no-op when the instance doesn't have `VK_EXT_debug_utils` enabled.
+pub struct DebugNames {
+    debug_utils: Option<DebugUtils>,
+    device: vk::Device,
+}
+
+impl DebugNames {
+    pub fn new(debug_utils: Option<DebugUtils>, device: &ash::Device) -> Self {
+        Self {
+            debug_utils,
+            device: device.handle(),
+        }
+    }
+
+    pub unsafe fn name<H: vk::Handle>(&self, object: H, name: &str) -> Result<()> {
+        let Some(debug_utils) = &self.debug_utils else {
+            return Ok(());
+        };
+        let c_name = CString::new(name).unwrap();
+        debug_utils
+            .set_debug_utils_object_name(
+                self.device,
+                &vk::DebugUtilsObjectNameInfoEXT {
+                    object_type: H::TYPE,
+                    object_handle: object.as_raw(),
+                    p_object_name: c_name.as_ptr(),
+                    ..Default::default()
+                },
+            )
+            .context(format!("name {name}"))
+    }
+
+    /// Opens a region labelled `label` in `command_buffer`, closed by [`Self::end_label`].
+    pub unsafe fn begin_label(&self, command_buffer: vk::CommandBuffer, label: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        let label = CString::new(label).unwrap();
+        debug_utils.cmd_begin_debug_utils_label(
+            command_buffer,
+            &vk::DebugUtilsLabelEXT {
+                p_label_name: label.as_ptr(),
+                ..Default::default()
+            },
+        );
+    }
+
+    pub unsafe fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils) = &self.debug_utils {
+            debug_utils.cmd_end_debug_utils_label(command_buffer);
+        }
+    }
+}
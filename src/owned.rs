@@ -0,0 +1,287 @@
+//! Owned Vulkan objects that are destroyed on drop.
+//!
+//! Every device-level object holds an `Rc` of the [`Device`] it was created from, and the device holds one of the
+//! [`Instance`], so the device outlives all of its objects and the instance outlives the device no matter in which
+//! order the owners go away.
+//!
+//! Once a submission has timed out, the GPU may still be executing it forever, and neither waiting for it nor
+//! destroying what it uses is safe. The device is then marked as hung, and every object of it is leaked instead.
+
+use std::{cell::Cell, ops::Deref, rc::Rc};
+
+use ash::vk;
+
+use crate::memory::Placement;
+
+pub struct Instance {
+    pub entry: ash::Entry,
+    instance: ash::Instance,
+}
+
+impl Instance {
+    pub fn new(entry: ash::Entry, instance: ash::Instance) -> Rc<Self> {
+        Rc::new(Self { entry, instance })
+    }
+}
+
+impl Deref for Instance {
+    type Target = ash::Instance;
+
+    fn deref(&self) -> &ash::Instance {
+        &self.instance
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe { self.instance.destroy_instance(None) };
+    }
+}
+
+pub struct Device {
+    device: ash::Device,
+    _instance: Rc<Instance>,
+    hung: Cell<bool>,
+}
+
+impl Device {
+    pub fn new(instance: &Rc<Instance>, device: ash::Device) -> Rc<Self> {
+        Rc::new(Self {
+            device,
+            _instance: instance.clone(),
+            hung: Cell::new(false),
+        })
+    }
+
+    /// Records that a submission didn't complete in time, so that the device and its objects are leaked.
+    pub fn mark_hung(&self) {
+        self.hung.set(true);
+    }
+
+    pub fn hung(&self) -> bool {
+        self.hung.get()
+    }
+}
+
+impl Deref for Device {
+    type Target = ash::Device;
+
+    fn deref(&self) -> &ash::Device {
+        &self.device
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        if self.hung() {
+            // The instance may not be destroyed before the device either.
+            std::mem::forget(self._instance.clone());
+            return;
+        }
+        unsafe {
+            // Nothing may still be executing when the device goes away. A lost device fails the wait but must still
+            // be destroyed.
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_device(None);
+        }
+    }
+}
+
+/// A device memory allocation, either dedicated to one buffer or a block that several buffers are sub-allocated
+/// from. Freed once the last buffer bound to it is dropped.
+pub struct Memory {
+    device: Rc<Device>,
+    /// Debug name of the allocation, also used in error messages.
+    pub name: String,
+    pub memory: vk::DeviceMemory,
+    pub size: u64,
+    pub memory_type_index: u32,
+    pub flags: vk::MemoryPropertyFlags,
+}
+
+impl Memory {
+    /// Takes ownership of `memory`, which was allocated with `size` bytes from memory type `memory_type_index`.
+    pub fn new(
+        device: &Rc<Device>,
+        name: &str,
+        memory: vk::DeviceMemory,
+        size: u64,
+        memory_type_index: u32,
+        flags: vk::MemoryPropertyFlags,
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            name: name.to_string(),
+            memory,
+            size,
+            memory_type_index,
+            flags,
+        }
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        if !self.device.hung() {
+            unsafe { self.device.free_memory(self.memory, None) };
+        }
+    }
+}
+
+/// A buffer bound to a range of a [`Memory`], which it keeps alive.
+pub struct Buffer {
+    device: Rc<Device>,
+    /// Debug name of the buffer, also used in error messages.
+    pub name: String,
+    pub buffer: vk::Buffer,
+    /// Size the buffer was created with.
+    pub size: u64,
+    pub placement: Placement,
+    memory: Option<Rc<Memory>>,
+    /// Offset of the buffer in its memory.
+    pub memory_offset: u64,
+}
+
+impl Buffer {
+    /// Takes ownership of `buffer`, which isn't bound to memory yet. The caller calls [`Buffer::set_memory`] once it
+    /// has bound it.
+    pub fn new(device: &Rc<Device>, name: &str, buffer: vk::Buffer, size: u64, placement: Placement) -> Self {
+        Self {
+            device: device.clone(),
+            name: name.to_string(),
+            buffer,
+            size,
+            placement,
+            memory: None,
+            memory_offset: 0,
+        }
+    }
+
+    /// Records that the buffer is bound to `memory` at `offset`.
+    pub fn set_memory(&mut self, memory: Rc<Memory>, offset: u64) {
+        self.memory = Some(memory);
+        self.memory_offset = offset;
+    }
+
+    /// The memory the buffer is bound to. Panics if it isn't bound yet.
+    pub fn memory(&self) -> &Memory {
+        self.memory.as_ref().expect("buffer is bound to memory")
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        // The memory is released after the buffer, when the field is dropped.
+        if !self.device.hung() {
+            unsafe { self.device.destroy_buffer(self.buffer, None) };
+        }
+    }
+}
+
+/// An acceleration structure along with the buffer backing it, which is destroyed right after it.
+pub struct AccelerationStructure {
+    loader: ash::extensions::khr::AccelerationStructure,
+    pub handle: vk::AccelerationStructureKHR,
+    /// The backing buffer, destroyed after the acceleration structure.
+    pub buffer: Buffer,
+}
+
+impl AccelerationStructure {
+    pub fn new(
+        loader: ash::extensions::khr::AccelerationStructure,
+        handle: vk::AccelerationStructureKHR,
+        buffer: Buffer,
+    ) -> Self {
+        Self {
+            loader,
+            handle,
+            buffer,
+        }
+    }
+}
+
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        if !self.buffer.device.hung() {
+            unsafe { self.loader.destroy_acceleration_structure(self.handle, None) };
+        }
+    }
+}
+
+/// Device-level handles that are destroyed with a single `vkDestroy*` call.
+pub trait Destroy: Copy {
+    unsafe fn destroy(self, device: &ash::Device);
+}
+
+impl Destroy for vk::Fence {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_fence(self, None);
+    }
+}
+
+impl Destroy for vk::CommandPool {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_command_pool(self, None);
+    }
+}
+
+impl Destroy for vk::ShaderModule {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_shader_module(self, None);
+    }
+}
+
+impl Destroy for vk::DescriptorSetLayout {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_descriptor_set_layout(self, None);
+    }
+}
+
+impl Destroy for vk::DescriptorPool {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_descriptor_pool(self, None);
+    }
+}
+
+impl Destroy for vk::PipelineLayout {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_pipeline_layout(self, None);
+    }
+}
+
+impl Destroy for vk::Pipeline {
+    unsafe fn destroy(self, device: &ash::Device) {
+        device.destroy_pipeline(self, None);
+    }
+}
+
+/// A handle destroyed through [`Destroy`] on drop. Dereferences to the raw handle.
+pub struct Owned<T: Destroy> {
+    device: Rc<Device>,
+    handle: T,
+}
+
+impl<T: Destroy> Owned<T> {
+    pub fn new(device: &Rc<Device>, handle: T) -> Self {
+        Self {
+            device: device.clone(),
+            handle,
+        }
+    }
+}
+
+impl<T: Destroy> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+impl<T: Destroy> Drop for Owned<T> {
+    fn drop(&mut self) {
+        if !self.device.hung() {
+            unsafe { self.handle.destroy(&self.device) };
+        }
+    }
+}
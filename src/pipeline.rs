@@ -0,0 +1,289 @@
+//! The ray tracing pipeline of the test shaders in `src/*.spv` and its descriptor set, and the dispatch of one ray
+//! through it.
+//!
+//! The pipeline has three shader groups: the raygen shader, the miss shader and a procedural hit group made of the
+//! intersection and closest hit shaders. Every shader copies its shader record into the results buffer, and the
+//! raygen shader reads the index of the miss and hit group record to use from a push constant.
+
+use std::ffi::c_void;
+
+use ash::vk;
+
+use crate::{
+    context::RtContext,
+    error::{Context, Result},
+    owned::{AccelerationStructure, Buffer, Owned},
+};
+
+/// Number of shader groups of the pipeline, in the order their handles are returned: raygen, miss, hit group.
+pub const GROUP_COUNT: u32 = 3;
+
+/// Fields are dropped in declaration order, which destroys the descriptor pool before the layouts it refers to.
+pub struct RtPipeline {
+    _descriptor_pool: Owned<vk::DescriptorPool>,
+    pub desc_set: vk::DescriptorSet,
+    pub pipeline: Owned<vk::Pipeline>,
+    pub layout: Owned<vk::PipelineLayout>,
+    _desc_set_layout: Owned<vk::DescriptorSetLayout>,
+    /// Handles of the [`GROUP_COUNT`] shader groups, `shaderGroupHandleSize` bytes each.
+    pub group_handles: Vec<u8>,
+}
+
+impl RtPipeline {
+    /// Creates the pipeline and a descriptor set that binds `tlas` and `results_buffer`, both of which must outlive
+    /// the pipeline.
+    pub unsafe fn new(ctx: &RtContext, tlas: &AccelerationStructure, results_buffer: &Buffer) -> Result<Self> {
+        let device = &ctx.device;
+        let names = &ctx.names;
+        let desc_set_layout = device
+            .create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo {
+                    flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+                    binding_count: 2,
+                    p_bindings: [
+                        vk::DescriptorSetLayoutBinding {
+                            binding: 0,
+                            descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                            descriptor_count: 1,
+                            stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                            ..Default::default()
+                        },
+                        vk::DescriptorSetLayoutBinding {
+                            binding: 1,
+                            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                            descriptor_count: 1,
+                            stage_flags: vk::ShaderStageFlags::RAYGEN_KHR | vk::ShaderStageFlags::INTERSECTION_KHR
+                                | vk::ShaderStageFlags::MISS_KHR | vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                            ..Default::default()
+                        },
+                    ]
+                    .as_slice()
+                    .as_ptr(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .context("create desc_set_layout")?;
+        let desc_set_layout = Owned::new(device, desc_set_layout);
+        names.name(*desc_set_layout, "desc_set_layout")?;
+        let layout = device
+            .create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo {
+                    set_layout_count: 1,
+                    p_set_layouts: &*desc_set_layout,
+                    push_constant_range_count: 1,
+                    p_push_constant_ranges: &vk::PushConstantRange {
+                        stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+                        offset: 0,
+                        size: 4,
+                    },
+                    ..Default::default()
+                },
+                None,
+            )
+            .context("create pipeline_layout")?;
+        let layout = Owned::new(device, layout);
+        names.name(*layout, "pipeline_layout")?;
+
+        let raygen_module = ctx.create_shader_module("test.rgen", include_bytes!("test.rgen.spv"))?;
+        let miss_module = ctx.create_shader_module("test.rmiss", include_bytes!("test.rmiss.spv"))?;
+        let rint_module = ctx.create_shader_module("test.rint", include_bytes!("test.rint.spv"))?;
+        let rchit_module = ctx.create_shader_module("test.rchit", include_bytes!("test.rchit.spv"))?;
+        let pipeline = ctx
+            .rtx_pipeline_loader
+            .create_ray_tracing_pipelines(
+                vk::DeferredOperationKHR::null(),
+                vk::PipelineCache::null(),
+                &[vk::RayTracingPipelineCreateInfoKHR {
+                    stage_count: 4,
+                    p_stages: [
+                        vk::PipelineShaderStageCreateInfo {
+                            stage: vk::ShaderStageFlags::RAYGEN_KHR,
+                            module: *raygen_module,
+                            p_name: c"main".as_ptr(),
+                            ..Default::default()
+                        },
+                        vk::PipelineShaderStageCreateInfo {
+                            stage: vk::ShaderStageFlags::MISS_KHR,
+                            module: *miss_module,
+                            p_name: c"main".as_ptr(),
+                            ..Default::default()
+                        },
+                        vk::PipelineShaderStageCreateInfo {
+                            stage: vk::ShaderStageFlags::INTERSECTION_KHR,
+                            module: *rint_module,
+                            p_name: c"main".as_ptr(),
+                            ..Default::default()
+                        },
+                        vk::PipelineShaderStageCreateInfo {
+                            stage: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                            module: *rchit_module,
+                            p_name: c"main".as_ptr(),
+                            ..Default::default()
+                        },
+                    ]
+                    .as_slice()
+                    .as_ptr(),
+                    group_count: GROUP_COUNT,
+                    p_groups: [
+                        vk::RayTracingShaderGroupCreateInfoKHR {
+                            ty: vk::RayTracingShaderGroupTypeKHR::GENERAL,
+                            general_shader: 0, // rgen
+                            any_hit_shader: vk::SHADER_UNUSED_KHR,
+                            closest_hit_shader: vk::SHADER_UNUSED_KHR,
+                            intersection_shader: vk::SHADER_UNUSED_KHR,
+                            ..Default::default()
+                        },
+                        vk::RayTracingShaderGroupCreateInfoKHR {
+                            ty: vk::RayTracingShaderGroupTypeKHR::GENERAL,
+                            general_shader: 1, // rmiss
+                            any_hit_shader: vk::SHADER_UNUSED_KHR,
+                            closest_hit_shader: vk::SHADER_UNUSED_KHR,
+                            intersection_shader: vk::SHADER_UNUSED_KHR,
+                            ..Default::default()
+                        },
+                        vk::RayTracingShaderGroupCreateInfoKHR {
+                            ty: vk::RayTracingShaderGroupTypeKHR::PROCEDURAL_HIT_GROUP,
+                            intersection_shader: 2,
+                            any_hit_shader: vk::SHADER_UNUSED_KHR,
+                            closest_hit_shader: 3,
+                            general_shader: vk::SHADER_UNUSED_KHR,
+                            ..Default::default()
+                        },
+                    ]
+                    .as_slice()
+                    .as_ptr(),
+                    max_pipeline_ray_recursion_depth: 1,
+                    layout: *layout,
+                    ..Default::default()
+                }],
+                None,
+            )
+            .context("create pipeline")?[0];
+        let pipeline = Owned::new(device, pipeline);
+        names.name(*pipeline, "pipeline")?;
+
+        let group_handles = ctx
+            .rtx_pipeline_loader
+            .get_ray_tracing_shader_group_handles(
+                *pipeline,
+                0,
+                GROUP_COUNT,
+                // On both NV and Intel, this is 32 * 3
+                ctx.rtx_pipeline_properties.shader_group_handle_size as usize * GROUP_COUNT as usize,
+            )
+            .context("get shader group handles")?;
+
+        let descriptor_pool = device
+            .create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo {
+                    max_sets: 1,
+                    pool_size_count: 2,
+                    p_pool_sizes: [
+                        vk::DescriptorPoolSize {
+                            ty: vk::DescriptorType::STORAGE_BUFFER,
+                            descriptor_count: 1,
+                        },
+                        vk::DescriptorPoolSize {
+                            ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                            descriptor_count: 1,
+                        },
+                    ]
+                    .as_slice()
+                    .as_ptr(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .context("create descriptor_pool")?;
+        let descriptor_pool = Owned::new(device, descriptor_pool);
+        names.name(*descriptor_pool, "descriptor_pool")?;
+        let desc_set = device
+            .allocate_descriptor_sets(&vk::DescriptorSetAllocateInfo {
+                descriptor_pool: *descriptor_pool,
+                descriptor_set_count: 1,
+                p_set_layouts: &*desc_set_layout,
+                ..Default::default()
+            })
+            .context("allocate desc_set")?[0];
+        names.name(desc_set, "desc_set")?;
+
+        device.update_descriptor_sets(
+            &[
+                vk::WriteDescriptorSet {
+                    dst_set: desc_set,
+                    dst_binding: 1,
+                    descriptor_count: 1,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    p_buffer_info: &vk::DescriptorBufferInfo {
+                        buffer: results_buffer.buffer,
+                        offset: 0,
+                        range: results_buffer.size,
+                    },
+                    ..Default::default()
+                },
+                vk::WriteDescriptorSet {
+                    dst_set: desc_set,
+                    dst_binding: 0,
+                    descriptor_count: 1,
+                    descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                    p_next: &vk::WriteDescriptorSetAccelerationStructureKHR {
+                        acceleration_structure_count: 1,
+                        p_acceleration_structures: &tlas.handle,
+                        ..Default::default()
+                    } as *const _ as *const c_void,
+                    ..Default::default()
+                },
+            ],
+            &[],
+        );
+
+        Ok(Self {
+            _descriptor_pool: descriptor_pool,
+            desc_set,
+            pipeline,
+            layout,
+            _desc_set_layout: desc_set_layout,
+            group_handles,
+        })
+    }
+
+    /// Records a 1x1x1 `cmd_trace_rays` with the SBT `regions` (raygen, miss, hit, callable), telling the raygen
+    /// shader to use the miss and hit group records at `record_index`.
+    pub unsafe fn cmd_trace(
+        &self,
+        ctx: &RtContext,
+        command_buffer: vk::CommandBuffer,
+        regions: &[vk::StridedDeviceAddressRegionKHR; 4],
+        record_index: u32,
+    ) {
+        let device = &ctx.device;
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::RAY_TRACING_KHR, *self.pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::RAY_TRACING_KHR,
+            *self.layout,
+            0,
+            &[self.desc_set],
+            &[],
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            *self.layout,
+            vk::ShaderStageFlags::RAYGEN_KHR,
+            0,
+            &record_index.to_ne_bytes(),
+        );
+        let [raygen_region, miss_region, hit_region, callable_region] = regions;
+        ctx.rtx_pipeline_loader.cmd_trace_rays(
+            command_buffer,
+            raygen_region,
+            miss_region,
+            hit_region,
+            callable_region,
+            1,
+            1,
+            1,
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Instance creation, physical device discovery and the capability checks run before a device is created, so that
+//! a GPU without ray tracing support is reported as skipped instead of failing somewhere inside `vkCreateDevice` or
+//! the pipeline setup.
+
+use std::{
+    env,
+    ffi::{c_void, CStr},
+    fmt,
+    path::PathBuf,
+    rc::Rc,
+    sync::Arc,
+};
+
+use ash::{extensions::ext::DebugUtils, vk};
+
+use crate::{
+    error::{Context, Error, Result},
+    owned::Instance,
+    validation::{self, Validation, ValidationLog},
+};
+
+/// Device extensions the harness enables.
+pub const REQUIRED_EXTENSIONS: [&CStr; 3] = [
+    ash::extensions::khr::AccelerationStructure::name(),
+    ash::extensions::khr::RayTracingPipeline::name(),
+    ash::extensions::khr::DeferredHostOperations::name(),
+];
+
+/// The Vulkan version the instance is created with. `VkPhysicalDeviceVulkan13Features` is chained into device
+/// creation, so the device must support it too.
+pub const API_VERSION: u32 = vk::make_api_version(0, 1, 3, 0);
+
+/// Why the tests can't run on this system. This isn't a failure of the driver under test, so it's reported without
+/// a non-zero exit status.
+#[derive(Debug)]
+pub struct Skipped {
+    pub missing: Vec<String>,
+}
+
+impl Skipped {
+    pub fn missing(what: &str) -> Self {
+        Self {
+            missing: vec![what.to_string()],
+        }
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped: missing {}", self.missing.join(", "))
+    }
+}
+
+/// The instance everything else is created from.
+pub struct Vulkan {
+    pub instance: Rc<Instance>,
+    /// Set when the instance has `VK_EXT_debug_utils` enabled, which it does whenever the loader offers it.
+    pub debug_utils: Option<DebugUtils>,
+    pub validation: Option<Validation>,
+}
+
+/// Which Vulkan loader library and ICD to use, to compare driver builds side by side. The defaults are the system
+/// loader and whatever ICDs it finds.
+#[derive(Clone, Debug, Default)]
+pub struct Loader {
+    /// Path of the loader library, e.g. `libvulkan.so.1` of a custom build.
+    pub library: Option<PathBuf>,
+    /// Path of the one ICD manifest the loader should use, e.g. the `lvp_icd.x86_64.json` of a patched Mesa.
+    pub icd: Option<PathBuf>,
+}
+
+/// Environment variables that make the loader use only the listed ICD manifests. `VK_DRIVER_FILES` replaced
+/// `VK_ICD_FILENAMES`, which older loaders still need.
+const ICD_VARIABLES: [&str; 2] = ["VK_DRIVER_FILES", "VK_ICD_FILENAMES"];
+
+impl Loader {
+    /// Loads the loader library, pointed at the ICD if one was given. Must be called before any other thread reads
+    /// the environment.
+    pub unsafe fn load(&self) -> Result<ash::Entry> {
+        if let Some(icd) = &self.icd {
+            if !icd.is_file() {
+                return Err(Error::Usage(format!("ICD manifest {} doesn't exist", icd.display())));
+            }
+            for variable in ICD_VARIABLES {
+                env::set_var(variable, icd);
+            }
+        }
+        match &self.library {
+            Some(library) => ash::Entry::load_from(library)
+                .map_err(|err| Error::Usage(format!("can't load Vulkan loader {}: {err}", library.display()))),
+            None => ash::Entry::load().map_err(|_| Skipped::missing("Vulkan loader").into()),
+        }
+    }
+}
+
+/// Names the loader and ICD for reports. An ICD chosen through the environment rather than [`Loader::icd`] is
+/// reported as well.
+impl fmt::Display for Loader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.library {
+            Some(library) => write!(f, "Vulkan loader: {}", library.display())?,
+            None => write!(f, "Vulkan loader: system default")?,
+        }
+        let from_env = ICD_VARIABLES
+            .iter()
+            .find_map(|variable| Some((variable, env::var_os(variable)?)));
+        match (&self.icd, from_env) {
+            (Some(icd), _) => write!(f, ", ICD: {}", icd.display()),
+            (None, Some((variable, icds))) => write!(f, ", ICD: {} (from {variable})", icds.to_string_lossy()),
+            (None, None) => write!(f, ", ICD: any the loader finds"),
+        }
+    }
+}
+
+/// Loads `loader` and creates an instance for [`API_VERSION`]. With `validate`, the Khronos validation layer is
+/// enabled if it's installed, and [`Vulkan::validation`] collects its messages. It stays `None` if the layer isn't
+/// installed.
+pub unsafe fn create_instance(loader: &Loader, validate: bool) -> Result<Vulkan> {
+    create_instance_with(loader.load()?, validate)
+}
+
+/// Like [`create_instance`], with a loader the caller loaded itself, e.g. with `ash::Entry::load_from`.
+pub unsafe fn create_instance_with(entry: ash::Entry, validate: bool) -> Result<Vulkan> {
+    let validate = validate && validation::layer_installed(&entry)?;
+    let layers = if validate { vec![validation::LAYER_NAME.as_ptr()] } else { vec![] };
+    // The validation layer provides VK_EXT_debug_utils itself, so it may not be listed by the loader.
+    let debug_utils = validate
+        || entry
+            .enumerate_instance_extension_properties(None)
+            .context("enumerate instance extensions")?
+            .iter()
+            .any(|extension| CStr::from_ptr(extension.extension_name.as_ptr()) == DebugUtils::name());
+    let extensions = if debug_utils { vec![DebugUtils::name().as_ptr()] } else { vec![] };
+    // Declared before the instance so that it outlives it, since the chained messenger also reports its destruction.
+    let log = Arc::new(ValidationLog::default());
+    let messenger_info = validation::messenger_info(&log);
+    let instance = match entry.create_instance(
+        &vk::InstanceCreateInfo {
+            p_next: if validate { &messenger_info as *const _ as *const c_void } else { std::ptr::null() },
+            p_application_info: &vk::ApplicationInfo {
+                api_version: API_VERSION,
+                ..Default::default()
+            },
+            enabled_layer_count: layers.len() as u32,
+            pp_enabled_layer_names: layers.as_ptr(),
+            enabled_extension_count: extensions.len() as u32,
+            pp_enabled_extension_names: extensions.as_ptr(),
+            ..Default::default()
+        },
+        None,
+    ) {
+        Ok(instance) => instance,
+        Err(vk::Result::ERROR_INCOMPATIBLE_DRIVER) => return Err(Skipped::missing("Vulkan driver").into()),
+        Err(result) => return Err(result).context("create instance"),
+    };
+    let instance = Instance::new(entry, instance);
+    let debug_utils = debug_utils.then(|| DebugUtils::new(&instance.entry, &instance));
+    let validation = match validate {
+        true => Some(Validation::new(&instance, debug_utils.clone().unwrap(), log)?),
+        false => None,
+    };
+    Ok(Vulkan {
+        instance,
+        debug_utils,
+        validation,
+    })
+}
+
+/// The identifiers of one physical device, as printed by `list-devices` and matched by `--device`.
+pub struct DeviceInfo {
+    pub handle: vk::PhysicalDevice,
+    /// Position in the `vkEnumeratePhysicalDevices` list.
+    pub index: usize,
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub uuid: [u8; 16],
+}
+
+impl DeviceInfo {
+    /// The driver version in the vendor's own encoding, which for some vendors isn't `VK_MAKE_API_VERSION`.
+    pub fn driver_version(&self) -> String {
+        let version = self.driver_version;
+        match self.vendor_id {
+            // NVIDIA
+            0x10DE => format!(
+                "{}.{}.{}.{}",
+                version >> 22,
+                (version >> 14) & 0xFF,
+                (version >> 6) & 0xFF,
+                version & 0x3F
+            ),
+            // Intel on Windows
+            0x8086 if cfg!(windows) => format!("{}.{}", version >> 14, version & 0x3FFF),
+            _ => format!(
+                "{}.{}.{}",
+                vk::api_version_major(version),
+                vk::api_version_minor(version),
+                vk::api_version_patch(version)
+            ),
+        }
+    }
+
+    pub fn uuid(&self) -> String {
+        let hex = self.uuid.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+
+    /// Whether `selector` names this device. A number is taken as the index; anything else may be the UUID (with or
+    /// without dashes), the `vendor:device` ID pair in hex, or part of the device name, all case-insensitive.
+    pub fn matches(&self, selector: &str) -> bool {
+        if let Ok(index) = selector.parse::<usize>() {
+            return index == self.index;
+        }
+        let selector = selector.to_lowercase();
+        selector.replace('-', "") == self.uuid().replace('-', "")
+            || selector == format!("{:04x}:{:04x}", self.vendor_id, self.device_id)
+            || self.name.to_lowercase().contains(&selector)
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} [{:04x}:{:04x}] driver {} uuid {}",
+            self.index,
+            self.name,
+            self.vendor_id,
+            self.device_id,
+            self.driver_version(),
+            self.uuid()
+        )
+    }
+}
+
+/// Every physical device of `instance`, in enumeration order.
+pub unsafe fn devices(instance: &ash::Instance) -> Result<Vec<DeviceInfo>> {
+    let handles = instance.enumerate_physical_devices().context("enumerate physical devices")?;
+    Ok(handles
+        .into_iter()
+        .enumerate()
+        .map(|(index, handle)| {
+            let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+            let mut properties = vk::PhysicalDeviceProperties2::builder()
+                .push_next(&mut id_properties)
+                .build();
+            instance.get_physical_device_properties2(handle, &mut properties);
+            let properties = properties.properties;
+            DeviceInfo {
+                handle,
+                index,
+                name: CStr::from_ptr(properties.device_name.as_ptr()).to_string_lossy().into_owned(),
+                vendor_id: properties.vendor_id,
+                device_id: properties.device_id,
+                driver_version: properties.driver_version,
+                uuid: id_properties.device_uuid,
+            }
+        })
+        .collect())
+}
+
+/// Picks the device `selector` names, or the first one if there's no selector.
+pub fn select_device<'a>(devices: &'a [DeviceInfo], selector: Option<&str>) -> Result<&'a DeviceInfo> {
+    let Some(selector) = selector else {
+        return devices.first().ok_or_else(|| Skipped::missing("Vulkan device").into());
+    };
+    let matching = devices.iter().filter(|device| device.matches(selector)).collect::<Vec<_>>();
+    match matching[..] {
+        [device] => Ok(device),
+        [] => Err(Error::Usage(format!("no device matches {selector:?}, see list-devices"))),
+        _ => Err(Error::Usage(format!(
+            "{selector:?} matches {} devices, select one by index or UUID",
+            matching.len()
+        ))),
+    }
+}
+
+/// A queue family that supports compute, which `vkCmdTraceRaysKHR` requires.
+#[derive(Clone, Copy)]
+pub struct QueueFamily {
+    pub index: u32,
+    pub flags: vk::QueueFlags,
+}
+
+impl fmt::Display for QueueFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "queue family {} ({:?})", self.index, self.flags)
+    }
+}
+
+/// Every compute-capable queue family of `pdevice`, in index order. This includes dedicated async-compute families
+/// that lack `VK_QUEUE_GRAPHICS_BIT`.
+pub unsafe fn compute_queue_families(instance: &ash::Instance, pdevice: vk::PhysicalDevice) -> Vec<QueueFamily> {
+    instance
+        .get_physical_device_queue_family_properties(pdevice)
+        .iter()
+        .zip(0..)
+        .filter(|(properties, _)| properties.queue_flags.contains(vk::QueueFlags::COMPUTE))
+        .map(|(properties, index)| QueueFamily {
+            index,
+            flags: properties.queue_flags,
+        })
+        .collect()
+}
+
+/// Lists everything the harness needs that `pdevice` doesn't support: the API version, device extensions and
+/// features. An empty list means the device can run the tests.
+pub unsafe fn missing_capabilities(instance: &ash::Instance, pdevice: vk::PhysicalDevice) -> Result<Vec<String>> {
+    let mut missing = Vec::new();
+
+    let properties = instance.get_physical_device_properties(pdevice);
+    if properties.api_version < API_VERSION {
+        missing.push("Vulkan 1.3".to_string());
+        // The 1.3 feature structs below can't be queried on an older device.
+        return Ok(missing);
+    }
+
+    let extensions = instance
+        .enumerate_device_extension_properties(pdevice)
+        .context("enumerate device extensions")?;
+    for required in REQUIRED_EXTENSIONS {
+        let supported = extensions
+            .iter()
+            .any(|extension| CStr::from_ptr(extension.extension_name.as_ptr()) == required);
+        if !supported {
+            missing.push(required.to_str().unwrap().to_string());
+        }
+    }
+
+    let mut rtx_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
+    let mut accel_struct_features = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+    let mut v12_features = vk::PhysicalDeviceVulkan12Features::default();
+    let mut v13_features = vk::PhysicalDeviceVulkan13Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut rtx_features)
+        .push_next(&mut accel_struct_features)
+        .push_next(&mut v12_features)
+        .push_next(&mut v13_features)
+        .build();
+    instance.get_physical_device_features2(pdevice, &mut features);
+    for (name, supported) in [
+        ("rayTracingPipeline", rtx_features.ray_tracing_pipeline),
+        ("accelerationStructure", accel_struct_features.acceleration_structure),
+        ("bufferDeviceAddress", v12_features.buffer_device_address),
+        ("synchronization2", v13_features.synchronization2),
+    ] {
+        if supported != vk::TRUE {
+            missing.push(name.to_string());
+        }
+    }
+    if compute_queue_families(instance, pdevice).is_empty() {
+        missing.push("compute queue".to_string());
+    }
+    Ok(missing)
+}
+
+/// Whether `pdevice` offers `VK_EXT_device_fault` with its `deviceFault` feature, which [`crate::fault`] needs to
+/// explain a lost device. The tests run without it.
+pub unsafe fn device_fault_supported(instance: &ash::Instance, pdevice: vk::PhysicalDevice) -> Result<bool> {
+    let extensions = instance
+        .enumerate_device_extension_properties(pdevice)
+        .context("enumerate device extensions")?;
+    if !extensions
+        .iter()
+        .any(|extension| CStr::from_ptr(extension.extension_name.as_ptr()) == vk::ExtDeviceFaultFn::name())
+    {
+        return Ok(false);
+    }
+    let mut fault_features = vk::PhysicalDeviceFaultFeaturesEXT::default();
+    let mut features = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut fault_features)
+        .build();
+    instance.get_physical_device_features2(pdevice, &mut features);
+    Ok(fault_features.device_fault == vk::TRUE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(index: usize, name: &str, vendor_id: u32, device_id: u32) -> DeviceInfo {
+        DeviceInfo {
+            handle: vk::PhysicalDevice::null(),
+            index,
+            name: name.to_string(),
+            vendor_id,
+            device_id,
+            driver_version: vk::make_api_version(0, 24, 1, 3),
+            uuid: [index as u8; 16],
+        }
+    }
+
+    fn devices() -> Vec<DeviceInfo> {
+        vec![
+            device(0, "Intel(R) UHD Graphics 770", 0x8086, 0x4680),
+            device(1, "Intel(R) Arc(TM) A770 Graphics", 0x8086, 0x56a0),
+            device(2, "llvmpipe (LLVM 17.0.6, 256 bits)", 0x10005, 0),
+        ]
+    }
+
+    fn select(selector: Option<&str>) -> std::result::Result<usize, String> {
+        select_device(&devices(), selector)
+            .map(|device| device.index)
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn selects_by_any_identifier() {
+        assert_eq!(select(None), Ok(0));
+        assert_eq!(select(Some("2")), Ok(2));
+        // A number is always an index, even if it also appears in a name.
+        assert_eq!(select(Some("770")).unwrap_err(), "no device matches \"770\", see list-devices");
+        assert_eq!(select(Some("arc")), Ok(1));
+        assert_eq!(select(Some("8086:56A0")), Ok(1));
+        assert_eq!(select(Some("01010101-0101-0101-0101-010101010101")), Ok(1));
+        assert_eq!(select(Some("02020202020202020202020202020202")), Ok(2));
+        assert!(select(Some("intel")).unwrap_err().contains("matches 2 devices"));
+    }
+
+    #[test]
+    fn formats_identifiers() {
+        let device = device(1, "Intel(R) Arc(TM) A770 Graphics", 0x8086, 0x56a0);
+        assert_eq!(device.uuid(), "01010101-0101-0101-0101-010101010101");
+        if !cfg!(windows) {
+            assert_eq!(
+                device.to_string(),
+                "1: Intel(R) Arc(TM) A770 Graphics [8086:56a0] driver 24.1.3 uuid 01010101-0101-0101-0101-010101010101"
+            );
+        }
+    }
+}
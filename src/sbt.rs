@@ -0,0 +1,294 @@
+//! Shader binding table layout.
+//!
+//! [`SbtBuilder`] collects the shader records of each SBT region and derives strides and region offsets from
+//! `VkPhysicalDeviceRayTracingPipelinePropertiesKHR`. The resulting [`SbtLayout`] is plain data: it can be written
+//! into any mapped buffer and turned into the four `VkStridedDeviceAddressRegionKHR` for `cmd_trace_rays` once the
+//! device address of that buffer is known.
+
+use std::ops::Range;
+
+use ash::vk;
+
+/// The four regions of a shader binding table, in the order `cmd_trace_rays` takes them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbtRegion {
+    Raygen,
+    Miss,
+    Hit,
+    Callable,
+}
+
+impl SbtRegion {
+    pub const ALL: [SbtRegion; 4] = [SbtRegion::Raygen, SbtRegion::Miss, SbtRegion::Hit, SbtRegion::Callable];
+}
+
+/// Placement of one SBT region, relative to the start of the table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionLayout {
+    pub offset: u64,
+    pub stride: u64,
+    pub size: u64,
+}
+
+pub struct SbtBuilder<'a> {
+    handle_size: u64,
+    handle_alignment: u64,
+    base_alignment: u64,
+    max_stride: u64,
+    group_handles: &'a [u8],
+    /// Shader records of each region: the group handle followed by the record payload.
+    records: [Vec<Vec<u8>>; 4],
+    strides: [Option<u64>; 4],
+}
+
+impl<'a> SbtBuilder<'a> {
+    /// `group_handles` is the blob returned by `vkGetRayTracingShaderGroupHandlesKHR`, starting at group 0.
+    pub fn new(properties: &vk::PhysicalDeviceRayTracingPipelinePropertiesKHR, group_handles: &'a [u8]) -> Self {
+        Self {
+            handle_size: properties.shader_group_handle_size as u64,
+            handle_alignment: properties.shader_group_handle_alignment as u64,
+            base_alignment: properties.shader_group_base_alignment as u64,
+            max_stride: properties.max_shader_group_stride as u64,
+            group_handles,
+            records: Default::default(),
+            strides: [None; 4],
+        }
+    }
+
+    /// Uses `stride` for `region` instead of the smallest stride that fits its records.
+    pub fn stride(mut self, region: SbtRegion, stride: u64) -> Self {
+        self.strides[region as usize] = Some(stride);
+        self
+    }
+
+    /// Appends a record for shader group `group` to `region`, with `data` placed right after the group handle.
+    /// The raygen region holds a single record, so pushing to it again replaces the previous one.
+    pub fn push(mut self, region: SbtRegion, group: u32, data: &[u8]) -> Self {
+        let start = group as usize * self.handle_size as usize;
+        let handle = &self.group_handles[start..start + self.handle_size as usize];
+        let records = &mut self.records[region as usize];
+        if region == SbtRegion::Raygen {
+            records.clear();
+        }
+        records.push([handle, data].concat());
+        self
+    }
+
+    /// Appends a record with a null (all zero) shader group handle to `region`. Tracing a null miss or hit group
+    /// record is a no-op.
+    pub fn push_null(mut self, region: SbtRegion) -> Self {
+        self.records[region as usize].push(vec![0; self.handle_size as usize]);
+        self
+    }
+
+    pub fn build(self) -> SbtLayout {
+        let mut regions = [RegionLayout::default(); 4];
+        let mut end = 0;
+        for region in SbtRegion::ALL {
+            let records = &self.records[region as usize];
+            if records.is_empty() {
+                continue;
+            }
+            let record_size = records.iter().map(Vec::len).max().unwrap() as u64;
+            let stride = self.strides[region as usize].unwrap_or_else(|| align_up(record_size, self.handle_alignment));
+            assert!(
+                stride >= record_size,
+                "{region:?} stride {stride} is smaller than its largest record ({record_size} bytes)"
+            );
+            assert!(
+                stride <= self.max_stride,
+                "{region:?} stride {stride} exceeds maxShaderGroupStride ({})",
+                self.max_stride
+            );
+            let offset = align_up(end, self.base_alignment);
+            let size = stride * records.len() as u64;
+            regions[region as usize] = RegionLayout { offset, stride, size };
+            end = offset + size;
+        }
+        SbtLayout {
+            regions,
+            size: end,
+            handle_size: self.handle_size,
+            records: self.records,
+        }
+    }
+}
+
+pub struct SbtLayout {
+    regions: [RegionLayout; 4],
+    size: u64,
+    handle_size: u64,
+    records: [Vec<Vec<u8>>; 4],
+}
+
+impl SbtLayout {
+    /// Number of bytes the table occupies, from the start of the raygen region to the end of the last region.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn handle_size(&self) -> u64 {
+        self.handle_size
+    }
+
+    /// Every record of the table as `(region, index in region, bytes)`, where the bytes are the record's range
+    /// relative to the start of the table: the group handle followed by the payload.
+    pub fn records(&self) -> impl Iterator<Item = (SbtRegion, usize, Range<usize>)> + '_ {
+        SbtRegion::ALL.into_iter().flat_map(move |region| {
+            let layout = self.regions[region as usize];
+            self.records[region as usize]
+                .iter()
+                .enumerate()
+                .map(move |(i, record)| {
+                    let start = (layout.offset + layout.stride * i as u64) as usize;
+                    (region, i, start..start + record.len())
+                })
+        })
+    }
+
+    /// Copies every record to its place in `dst`, which must be at least [`Self::size`] bytes long. Bytes between
+    /// records are left untouched.
+    pub fn write(&self, dst: &mut [u8]) {
+        assert!(dst.len() as u64 >= self.size);
+        for (region, i, range) in self.records() {
+            dst[range].copy_from_slice(&self.records[region as usize][i]);
+        }
+    }
+
+    /// The regions to pass to `cmd_trace_rays` when the table was written at device address `base`. Empty regions
+    /// are left zeroed.
+    pub fn device_regions(&self, base: vk::DeviceAddress) -> [vk::StridedDeviceAddressRegionKHR; 4] {
+        self.regions.map(|layout| {
+            if layout.size == 0 {
+                return vk::StridedDeviceAddressRegionKHR::default();
+            }
+            vk::StridedDeviceAddressRegionKHR {
+                device_address: base + layout.offset,
+                stride: layout.stride,
+                size: layout.size,
+            }
+        })
+    }
+}
+
+pub fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(base_alignment: u32) -> vk::PhysicalDeviceRayTracingPipelinePropertiesKHR {
+        vk::PhysicalDeviceRayTracingPipelinePropertiesKHR {
+            shader_group_handle_size: 32,
+            shader_group_handle_alignment: 32,
+            shader_group_base_alignment: base_alignment,
+            max_shader_group_stride: 4096,
+            ..Default::default()
+        }
+    }
+
+    fn handles() -> Vec<u8> {
+        (0..4).flat_map(|group| [group as u8 + 1; 32]).collect()
+    }
+
+    /// (offset, stride, size) of every region when the table starts at device address 0.
+    fn regions(layout: &SbtLayout) -> [(u64, u64, u64); 4] {
+        layout
+            .device_regions(0)
+            .map(|region| (region.device_address, region.stride, region.size))
+    }
+
+    #[test]
+    fn regions_follow_base_alignment() {
+        let handles = handles();
+        let layout = SbtBuilder::new(&properties(64), &handles)
+            .push(SbtRegion::Raygen, 0, &[0xAA; 64])
+            .push(SbtRegion::Miss, 1, &[])
+            .push(SbtRegion::Hit, 2, &[])
+            .build();
+        assert_eq!(regions(&layout), [(0, 96, 96), (128, 32, 32), (192, 32, 32), (0, 0, 0)]);
+        assert_eq!(layout.size(), 224);
+    }
+
+    #[test]
+    fn stride_fits_largest_record() {
+        let handles = handles();
+        let layout = SbtBuilder::new(&properties(32), &handles)
+            .push(SbtRegion::Miss, 1, &[0; 4])
+            .push(SbtRegion::Miss, 1, &[0; 40])
+            .push(SbtRegion::Callable, 3, &[])
+            .build();
+        assert_eq!(regions(&layout), [(0, 0, 0), (0, 96, 192), (0, 0, 0), (192, 32, 32)]);
+    }
+
+    #[test]
+    fn raygen_holds_one_record() {
+        let handles = handles();
+        let layout = SbtBuilder::new(&properties(64), &handles)
+            .push(SbtRegion::Raygen, 0, &[0; 64])
+            .push(SbtRegion::Raygen, 1, &[])
+            .build();
+        assert_eq!(regions(&layout)[0], (0, 32, 32));
+    }
+
+    #[test]
+    fn stride_override() {
+        let handles = handles();
+        let layout = SbtBuilder::new(&properties(64), &handles)
+            .stride(SbtRegion::Raygen, 160)
+            .stride(SbtRegion::Hit, 64)
+            .push(SbtRegion::Raygen, 0, &[0; 64])
+            .push_null(SbtRegion::Hit)
+            .push(SbtRegion::Hit, 2, &[])
+            .build();
+        assert_eq!(regions(&layout), [(0, 160, 160), (0, 0, 0), (192, 64, 128), (0, 0, 0)]);
+
+        let mut dst = vec![0xFF; layout.size() as usize];
+        layout.write(&mut dst);
+        assert_eq!(&dst[192..224], &[0; 32]);
+        assert_eq!(&dst[256..288], &[3; 32]);
+    }
+
+    #[test]
+    #[should_panic(expected = "smaller than its largest record")]
+    fn stride_too_small() {
+        let handles = handles();
+        SbtBuilder::new(&properties(64), &handles)
+            .stride(SbtRegion::Raygen, 32)
+            .push(SbtRegion::Raygen, 0, &[0; 64])
+            .build();
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds maxShaderGroupStride")]
+    fn stride_too_large() {
+        let handles = handles();
+        SbtBuilder::new(&properties(64), &handles)
+            .push(SbtRegion::Raygen, 0, &[0; 4096])
+            .build();
+    }
+
+    #[test]
+    fn write_and_device_regions() {
+        let handles = handles();
+        let layout = SbtBuilder::new(&properties(64), &handles)
+            .push(SbtRegion::Raygen, 0, &[0xAA; 8])
+            .push(SbtRegion::Hit, 2, &[])
+            .build();
+        let mut dst = vec![0; layout.size() as usize];
+        layout.write(&mut dst);
+        assert_eq!(&dst[..32], &[1; 32]);
+        assert_eq!(&dst[32..40], &[0xAA; 8]);
+        assert_eq!(&dst[40..64], &[0; 24]);
+        assert_eq!(&dst[64..96], &[3; 32]);
+
+        let [raygen, miss, hit, callable] = layout.device_regions(0x1000);
+        assert_eq!(raygen.device_address, 0x1000);
+        assert_eq!(raygen.size, raygen.stride);
+        assert_eq!(miss.device_address, 0);
+        assert_eq!(hit.device_address, 0x1040);
+        assert_eq!(callable.size, 0);
+    }
+}
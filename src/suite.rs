@@ -0,0 +1,192 @@
+//! Test cases that run against one shared [`Harness`], the foundation of a driver conformance suite.
+//!
+//! Each case implements [`RtTestCase`]: [`RtTestCase::setup`] writes what it needs into the harness buffers,
+//! [`RtTestCase::record`] records its commands into a command buffer that the runner submits, and
+//! [`RtTestCase::verify`] reads back and checks the results once the submission has completed. [`registry`] lists
+//! every case and [`run_suite`] runs all of them, or the ones a filter selects, one after the other on the same device.
+
+use std::fmt;
+
+use ash::vk;
+
+use crate::{
+    error::{Error, Result},
+    harness::{Harness, PreparedTrace, TraceParams, TraceResult},
+};
+
+pub trait RtTestCase {
+    /// Short unique name, which selects the case on the command line.
+    fn name(&self) -> &'static str;
+
+    /// One line on what the case checks.
+    fn describe(&self) -> String;
+
+    /// Writes the inputs of the case into the buffers of `harness`.
+    unsafe fn setup(&mut self, harness: &mut Harness) -> Result<()>;
+
+    /// Records the commands of the case into `command_buffer`, after [`RtTestCase::setup`].
+    unsafe fn record(&self, harness: &Harness, command_buffer: vk::CommandBuffer);
+
+    /// Reads back and checks the results, once the commands of [`RtTestCase::record`] have completed.
+    unsafe fn verify(&mut self, harness: &mut Harness) -> Result<Outcome>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The driver failed the case, with one line per problem found.
+    Failed(Vec<String>),
+}
+
+/// Every case of the suite, in the order they run.
+pub fn registry() -> Vec<Box<dyn RtTestCase>> {
+    vec![
+        Box::new(SbtRecords {
+            name: "sbt-records",
+            record_index: 0,
+            prepared: None,
+        }),
+        Box::new(SbtRecords {
+            name: "second-record",
+            record_index: 1,
+            prepared: None,
+        }),
+    ]
+}
+
+/// The outcome of every case [`run_suite`] ran.
+pub struct SuiteReport {
+    /// `(name, description, outcome)` of each case.
+    pub results: Vec<(&'static str, String, Outcome)>,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, _, outcome)| *outcome == Outcome::Passed)
+    }
+}
+
+impl fmt::Display for SuiteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, description, outcome) in &self.results {
+            match outcome {
+                Outcome::Passed => writeln!(f, "pass  {name}: {description}")?,
+                Outcome::Failed(problems) => {
+                    writeln!(f, "FAIL  {name}: {description}")?;
+                    for problem in problems {
+                        writeln!(f, "      {problem}")?;
+                    }
+                }
+            }
+        }
+        let failed = self.results.iter().filter(|(_, _, outcome)| *outcome != Outcome::Passed).count();
+        write!(f, "{failed} of {} cases failed", self.results.len())
+    }
+}
+
+/// The cases of `cases` whose name contains one of `filters`, or all of them without filters.
+pub fn select(cases: Vec<Box<dyn RtTestCase>>, filters: &[String]) -> Result<Vec<Box<dyn RtTestCase>>> {
+    if filters.is_empty() {
+        return Ok(cases);
+    }
+    let names = cases.iter().map(|case| case.name()).collect::<Vec<_>>().join(", ");
+    let selected = cases
+        .into_iter()
+        .filter(|case| filters.iter().any(|filter| case.name().contains(filter.as_str())))
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        return Err(Error::Usage(format!("no test case matches {filters:?}, the cases are {names}")));
+    }
+    Ok(selected)
+}
+
+/// Runs the cases of the [`registry`] selected by `filters` on `harness`. A GPU timeout or a lost device ends the
+/// run with an error naming the case, since nothing can run on the device afterwards.
+pub unsafe fn run_suite(harness: &mut Harness, filters: &[String]) -> Result<SuiteReport> {
+    let mut results = Vec::new();
+    for mut case in select(registry(), filters)? {
+        let outcome = run_case(harness, case.as_mut())
+            .map_err(|err| harness.explain_fault(err.with_params(format_args!("test case {}", case.name()))))?;
+        results.push((case.name(), case.describe(), outcome));
+    }
+    Ok(SuiteReport { results })
+}
+
+unsafe fn run_case(harness: &mut Harness, case: &mut dyn RtTestCase) -> Result<Outcome> {
+    case.setup(harness)?;
+    harness
+        .ctx
+        .execute(case.name(), |command_buffer| case.record(harness, command_buffer))?;
+    case.verify(harness)
+}
+
+/// Traces the test pipeline with the SBT at `shaderGroupBaseAlignment` and checks that every shader read its record
+/// correctly. The rays use the miss and hit group records at `record_index`, after that many null records.
+struct SbtRecords {
+    name: &'static str,
+    record_index: u32,
+    prepared: Option<PreparedTrace>,
+}
+
+impl RtTestCase for SbtRecords {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn describe(&self) -> String {
+        match self.record_index {
+            0 => "every shader reads its record with the SBT at shaderGroupBaseAlignment".to_string(),
+            index => format!("every shader reads its record with the miss and hit group records at index {index}"),
+        }
+    }
+
+    unsafe fn setup(&mut self, harness: &mut Harness) -> Result<()> {
+        let base_offset = harness.ctx.rtx_pipeline_properties.shader_group_base_alignment as usize;
+        self.prepared = Some(harness.prepare_trace(TraceParams {
+            record_index: self.record_index,
+            ..TraceParams::at_offset(base_offset)
+        })?);
+        Ok(())
+    }
+
+    unsafe fn record(&self, harness: &Harness, command_buffer: vk::CommandBuffer) {
+        harness.cmd_trace(command_buffer, self.prepared.as_ref().unwrap());
+    }
+
+    unsafe fn verify(&mut self, harness: &mut Harness) -> Result<Outcome> {
+        let result = harness.collect_trace(self.prepared.take().unwrap())?;
+        Ok(stage_outcome(&result))
+    }
+}
+
+/// Names the shaders that didn't read their record correctly, followed by where the corrupted words came from.
+fn stage_outcome(result: &TraceResult) -> Outcome {
+    if result.passed() {
+        return Outcome::Passed;
+    }
+    let mut problems = result
+        .failed_stages()
+        .into_iter()
+        .map(|stage| format!("{stage} shader didn't read its record"))
+        .collect::<Vec<_>>();
+    result.for_each_corruption(|report| problems.push(format!("{}: {}", report.name, report.verdict())));
+    Outcome::Failed(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(filters: &[&str]) -> Result<Vec<&'static str>> {
+        let filters = filters.iter().map(|filter| filter.to_string()).collect::<Vec<_>>();
+        Ok(select(registry(), &filters)?.iter().map(|case| case.name()).collect())
+    }
+
+    #[test]
+    fn selects_cases() {
+        assert_eq!(selected(&[]).unwrap(), ["sbt-records", "second-record"]);
+        assert_eq!(selected(&["second"]).unwrap(), ["second-record"]);
+        assert_eq!(selected(&["record"]).unwrap(), ["sbt-records", "second-record"]);
+        assert!(matches!(selected(&["stride"]), Err(Error::Usage(_))));
+    }
+}
@@ -0,0 +1,138 @@
+//! Opt-in Khronos validation, so that a failing run can be told apart from misuse of the API by the harness itself.
+//!
+//! Every message the debug-utils messengers receive is kept in a [`ValidationLog`], down to verbose ones and from
+//! instance creation to its destruction. Nothing is printed, callers decide what to show from
+//! [`ValidationLog::messages`]; [`ValidationLog::passed`] is false as soon as one of them has error severity.
+
+use std::{
+    ffi::{c_void, CStr},
+    fmt,
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use ash::{extensions::ext::DebugUtils, vk};
+
+use crate::{
+    error::{Context, Result},
+    owned::Instance,
+};
+
+pub const LAYER_NAME: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// Whether the validation layer is installed.
+pub fn layer_installed(entry: &ash::Entry) -> Result<bool> {
+    Ok(entry
+        .enumerate_instance_layer_properties()
+        .context("enumerate instance layers")?
+        .iter()
+        .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == LAYER_NAME))
+}
+
+pub struct Message {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub text: String,
+}
+
+/// Every message the messengers of one instance received, in order.
+#[derive(Default)]
+pub struct ValidationLog {
+    messages: Mutex<Vec<Message>>,
+}
+
+impl ValidationLog {
+    /// Every message received so far, in order.
+    pub fn messages(&self) -> MutexGuard<'_, Vec<Message>> {
+        self.messages.lock().unwrap()
+    }
+
+    fn count(&self, severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> usize {
+        self.messages().iter().filter(|message| message.severity == severity).count()
+    }
+
+    /// False if any error-severity message was received so far.
+    pub fn passed(&self) -> bool {
+        self.count(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) == 0
+    }
+}
+
+impl fmt::Display for ValidationLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "validation: {} errors, {} warnings, {} info and {} verbose messages",
+            self.count(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR),
+            self.count(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING),
+            self.count(vk::DebugUtilsMessageSeverityFlagsEXT::INFO),
+            self.count(vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE)
+        )
+    }
+}
+
+/// A messenger that records every message into `log`. Chained into `VkInstanceCreateInfo`, it also receives the
+/// messages of `vkCreateInstance` and `vkDestroyInstance`, so `log` must outlive the instance.
+pub fn messenger_info(log: &ValidationLog) -> vk::DebugUtilsMessengerCreateInfoEXT {
+    vk::DebugUtilsMessengerCreateInfoEXT {
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+            | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+            | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+            | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+            | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        pfn_user_callback: Some(callback),
+        p_user_data: log as *const ValidationLog as *mut c_void,
+        ..Default::default()
+    }
+}
+
+pub struct Validation {
+    debug_utils: DebugUtils,
+    messenger: vk::DebugUtilsMessengerEXT,
+    // The messenger must be destroyed before the instance, and the log must outlive both since the messenger chained
+    // into the instance reports its destruction.
+    _instance: Rc<Instance>,
+    log: Arc<ValidationLog>,
+}
+
+impl Validation {
+    /// Registers a messenger that records into `log` on `instance`, which must have been created with [`LAYER_NAME`]
+    /// enabled and [`messenger_info`] of the same `log` chained.
+    pub unsafe fn new(instance: &Rc<Instance>, debug_utils: DebugUtils, log: Arc<ValidationLog>) -> Result<Self> {
+        let messenger = debug_utils
+            .create_debug_utils_messenger(&messenger_info(&log), None)
+            .context("create debug messenger")?;
+        Ok(Self {
+            debug_utils,
+            messenger,
+            _instance: instance.clone(),
+            log,
+        })
+    }
+
+    /// The messages received so far. The log keeps collecting until the instance is destroyed, and can be read
+    /// after that.
+    pub fn log(&self) -> Arc<ValidationLog> {
+        self.log.clone()
+    }
+}
+
+impl Drop for Validation {
+    fn drop(&mut self) {
+        unsafe {
+            self.debug_utils.destroy_debug_utils_messenger(self.messenger, None);
+        }
+    }
+}
+
+unsafe extern "system" fn callback(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    _types: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    user_data: *mut c_void,
+) -> vk::Bool32 {
+    let text = CStr::from_ptr((*data).p_message).to_string_lossy().into_owned();
+    let log = &*(user_data as *const ValidationLog);
+    log.messages().push(Message { severity, text });
+    vk::FALSE
+}
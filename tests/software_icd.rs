@@ -0,0 +1,145 @@
+//! Runs the registered test cases on a software Vulkan implementation, so that regressions in the harness itself are
+//! caught on machines without a GPU.
+//!
+//! The implementation is chosen through the usual loader environment: `VK_ICD_FILENAMES` or `VK_DRIVER_FILES` if
+//! either is set, and Mesa's lavapipe from the standard manifest directories otherwise. `INTEL_ALIGNMENT_BUG_LOADER`
+//! names a Vulkan loader library to use instead of the system one, which must exist. When there is no system loader,
+//! no software ICD or no device with ray tracing, every test prints why and passes.
+
+use std::{
+    env,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use intel_alignment_bug::{
+    allocator::{self, SubAllocator},
+    context::RtContext,
+    error::Error,
+    harness::{Harness, SbtLocation, TraceParams},
+    memory::Placement,
+    probe::{self, Loader, Vulkan},
+    suite,
+};
+
+/// Directories the loader reads ICD manifests from on Linux.
+const MANIFEST_DIRS: [&str; 3] = ["/usr/share/vulkan/icd.d", "/usr/local/share/vulkan/icd.d", "/etc/vulkan/icd.d"];
+
+/// The lavapipe manifest, e.g. `lvp_icd.x86_64.json`, if Mesa installed one.
+fn lavapipe_manifest() -> Option<PathBuf> {
+    MANIFEST_DIRS
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("lvp_icd") && name.ends_with(".json"))
+        })
+}
+
+/// Points the loader at a software ICD unless the environment already chose one. Returns why the tests can't run
+/// otherwise.
+fn select_icd() -> Result<(), String> {
+    static SELECTED: OnceLock<Result<(), String>> = OnceLock::new();
+    SELECTED
+        .get_or_init(|| {
+            let chosen = ["VK_ICD_FILENAMES", "VK_DRIVER_FILES", "INTEL_ALIGNMENT_BUG_LOADER"]
+                .iter()
+                .any(|name| env::var_os(name).is_some());
+            if chosen {
+                return Ok(());
+            }
+            let manifest = lavapipe_manifest().ok_or("no software Vulkan ICD, set VK_ICD_FILENAMES to one")?;
+            // Only called once, before any test loads Vulkan.
+            env::set_var("VK_ICD_FILENAMES", manifest);
+            Ok(())
+        })
+        .clone()
+}
+
+/// Creates a harness on the first device of the selected ICD that can run the tests, with `configure` applied to
+/// its context first. Returns `None` after printing why if there is none.
+unsafe fn harness(configure: impl FnOnce(&mut RtContext)) -> Option<(Harness, Vulkan)> {
+    // Creating instances and devices concurrently is fine, but their output would interleave.
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Err(reason) = select_icd() {
+        println!("skipped: {reason}");
+        return None;
+    }
+    let loader = Loader {
+        library: env::var_os("INTEL_ALIGNMENT_BUG_LOADER").map(PathBuf::from),
+        icd: None,
+    };
+    let vulkan = match probe::create_instance(&loader, false) {
+        Ok(vulkan) => vulkan,
+        Err(Error::Skipped(skipped)) => {
+            println!("{skipped}");
+            return None;
+        }
+        Err(err) => panic!("{err}"),
+    };
+    println!("{loader}");
+    let devices = probe::devices(&vulkan.instance).unwrap();
+    let mut missing = Vec::new();
+    for device in &devices {
+        let device_missing = probe::missing_capabilities(&vulkan.instance, device.handle).unwrap();
+        if !device_missing.is_empty() {
+            missing.push(format!("{} lacks {}", device.name, device_missing.join(", ")));
+            continue;
+        }
+        let mut ctx = RtContext::new(&vulkan, device, false).unwrap();
+        configure(&mut ctx);
+        return Some((Harness::new(ctx).unwrap(), vulkan));
+    }
+    println!("skipped: no device with ray tracing ({})", missing.join("; "));
+    None
+}
+
+#[test]
+fn registered_cases_pass() {
+    unsafe {
+        let Some((mut harness, _vulkan)) = harness(|_| {}) else { return };
+        let report = suite::run_suite(&mut harness, &[]).unwrap();
+        assert!(report.passed(), "{report}");
+        assert_eq!(report.results.len(), suite::registry().len());
+    }
+}
+
+#[test]
+fn cases_pass_with_suballocation() {
+    unsafe {
+        let Some((mut harness, _vulkan)) = harness(|ctx| {
+            ctx.allocator = Some(SubAllocator::new(allocator::DEFAULT_BLOCK_SIZE));
+            ctx.force_non_coherent = true;
+        }) else {
+            return;
+        };
+        let report = suite::run_suite(&mut harness, &[]).unwrap();
+        assert!(report.passed(), "{report}");
+    }
+}
+
+#[test]
+fn every_placement_and_location_passes() {
+    unsafe {
+        let Some((mut harness, _vulkan)) = harness(|_| {}) else { return };
+        let base_alignment = harness.ctx.rtx_pipeline_properties.shader_group_base_alignment as u64;
+        for placement in Placement::ALL {
+            for location in SbtLocation::variants(4096) {
+                let result = harness
+                    .trace(TraceParams {
+                        placement,
+                        location,
+                        ..TraceParams::at_offset(base_alignment as usize)
+                    })
+                    .unwrap();
+                assert!(result.passed(), "{}", result.params);
+                assert!(result.sbt_address.is_multiple_of(base_alignment));
+            }
+        }
+    }
+}